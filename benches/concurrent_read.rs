@@ -0,0 +1,61 @@
+//! Read throughput of `ConcurrentFnMap` hits from several threads.
+//!
+//! Hits lock the map for reading by default and read a snapshot with `lock-free-read` feature.
+//!
+//! Run with `cargo bench --bench concurrent_read` for the `RwLock` baseline
+//! and with `cargo bench --bench concurrent_read --features lock-free-read` to compare.
+//! Maps cannot be shared between threads with `no-unsafe-marker` feature, so nothing is run.
+
+#![cfg_attr(feature = "no-unsafe-marker", allow(unused_imports, dead_code))]
+
+use std::{hint::black_box, thread, time::Instant};
+
+use fn_map::ConcurrentFnMap;
+
+const GETS: u32 = 2_000_000;
+
+#[cfg(not(feature = "no-unsafe-marker"))]
+fn bench(name: &str, threads: usize, get: impl Fn() + Sync) {
+    let started = Instant::now();
+    thread::scope(|scope| {
+        for _ in 0..threads {
+            scope.spawn(|| {
+                for _ in 0..GETS {
+                    get();
+                }
+            });
+        }
+    });
+
+    let elapsed = started.elapsed();
+    let gets = f64::from(GETS) * threads as f64;
+    println!(
+        "{name}, {threads} threads: {:.2} Mgets/s",
+        gets / elapsed.as_secs_f64() / 1e6
+    );
+}
+
+#[cfg(feature = "no-unsafe-marker")]
+fn main() {}
+
+#[cfg(not(feature = "no-unsafe-marker"))]
+fn main() {
+    let mode = if cfg!(feature = "lock-free-read") {
+        "lock-free-read"
+    } else {
+        "RwLock"
+    };
+    let max = thread::available_parallelism().map_or(4, |n| n.get().min(8));
+
+    let map = ConcurrentFnMap::new();
+    map.get(|| 1_u64);
+    map.get(|| 2_u64);
+
+    let mut threads = 1;
+    while threads <= max {
+        bench(mode, threads, || {
+            black_box(map.get(|| 1_u64));
+        });
+        threads *= 2;
+    }
+}
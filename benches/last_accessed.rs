@@ -0,0 +1,48 @@
+//! Repeated same-key gets with and without the last accessed value cache of `FnMap`.
+//!
+//! `LocalOnlyFnMap` stores values in the same raw map but looks up every get.
+//!
+//! Run with `cargo bench --bench last_accessed`.
+
+use std::{hint::black_box, time::Instant};
+
+use fn_map::{FnMap, LocalOnlyFnMap};
+
+const GETS: u32 = 10_000_000;
+
+fn bench(name: &str, mut get: impl FnMut()) {
+    let started = Instant::now();
+    for _ in 0..GETS {
+        get();
+    }
+
+    let elapsed = started.elapsed();
+    println!(
+        "{name}: {:.2} ns/get",
+        elapsed.as_nanos() as f64 / f64::from(GETS)
+    );
+}
+
+fn main() {
+    let cached = FnMap::new();
+    bench("FnMap, same key (cached)", || {
+        black_box(cached.get(|| 1_u64));
+    });
+
+    let uncached = LocalOnlyFnMap::new();
+    bench("LocalOnlyFnMap, same key (lookup)", || {
+        black_box(uncached.get(|| 1_u64));
+    });
+
+    // alternating keys miss the cache every get
+    let alternating = FnMap::new();
+    let mut even = false;
+    bench("FnMap, alternating keys (lookup)", || {
+        even = !even;
+        if even {
+            black_box(alternating.get(|| 1_u64));
+        } else {
+            black_box(alternating.get(|| 2_u64));
+        }
+    });
+}
@@ -0,0 +1,208 @@
+//! Drive sequence of map operations and check every value is dropped exactly once.
+//!
+//! Every allocation is tracked, so leaked or doubly freed memory of maps is caught as well as values.
+//!
+//! Run with `cargo fuzz run ops`, or under Miri by replaying artifacts.
+#![no_main]
+
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use arbitrary::Arbitrary;
+use fn_map::{ConcurrentFnMap, FnMap};
+use libfuzzer_sys::fuzz_target;
+
+// number of live tracked values
+static LIVE: AtomicUsize = AtomicUsize::new(0);
+
+#[global_allocator]
+static ALLOCATOR: Tracking = Tracking;
+
+// bytes allocated and not freed yet
+static ALLOCATED: AtomicUsize = AtomicUsize::new(0);
+
+const LIVE_MAGIC: usize = 0xA110_CA7E;
+const FREED_MAGIC: usize = 0xF2EE_D000;
+
+/// Allocator counting allocated bytes and checking every pointer is freed once
+///
+/// Each allocation is prefixed with a header holding magic number and size of the allocation.
+struct Tracking;
+
+impl Tracking {
+    /// layout of allocation including header and offset of returned pointer
+    fn layout(layout: Layout) -> (Layout, usize) {
+        let header = Layout::new::<[usize; 2]>();
+        let (layout, offset) = header.extend(layout).expect("allocation too large");
+
+        (layout.pad_to_align(), offset)
+    }
+}
+
+// SAFETY: allocations are delegated to System with layout including header
+unsafe impl GlobalAlloc for Tracking {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let (outer, offset) = Self::layout(layout);
+
+        // SAFETY: outer layout has non zero size
+        let base = unsafe { System.alloc(outer) };
+        if base.is_null() {
+            return base;
+        }
+
+        // SAFETY: header is in bounds and aligned at start of allocation
+        unsafe { base.cast::<[usize; 2]>().write([LIVE_MAGIC, layout.size()]) };
+        ALLOCATED.fetch_add(layout.size(), Ordering::Relaxed);
+
+        // SAFETY: offset is in bounds of allocation
+        unsafe { base.add(offset) }
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        let (outer, offset) = Self::layout(layout);
+
+        // SAFETY: ptr is returned by alloc using same layout, so it is offset from start of allocation
+        let base = unsafe { ptr.sub(offset) };
+        let header = base.cast::<[usize; 2]>();
+
+        // SAFETY: header is written on alloc
+        let [magic, size] = unsafe { header.read() };
+        match magic {
+            LIVE_MAGIC => {}
+            FREED_MAGIC => panic!("double free of {ptr:p}"),
+            _ => panic!("free of pointer {ptr:p} not allocated"),
+        }
+        assert_eq!(size, layout.size(), "free using different layout");
+
+        // SAFETY: header is in bounds and not freed yet
+        unsafe { header.write([FREED_MAGIC, size]) };
+        ALLOCATED.fetch_sub(size, Ordering::Relaxed);
+
+        // SAFETY: base is allocated by System using outer
+        unsafe { System.dealloc(base, outer) };
+    }
+}
+
+/// Value counting its instances
+#[derive(Debug, PartialEq)]
+struct Tracked<T>(T);
+
+impl<T> Tracked<T> {
+    fn new(value: T) -> Self {
+        LIVE.fetch_add(1, Ordering::Relaxed);
+        Self(value)
+    }
+}
+
+impl<T: Clone> Clone for Tracked<T> {
+    fn clone(&self) -> Self {
+        Self::new(self.0.clone())
+    }
+}
+
+impl<T> Drop for Tracked<T> {
+    fn drop(&mut self) {
+        LIVE.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+#[derive(Debug, Clone, Copy, Arbitrary)]
+enum Op {
+    Get(u8),
+    GetMut(u8),
+    Recompute(u8),
+    Remove(u8),
+    InvalidateType,
+    Clear,
+    Reset,
+    ConcurrentGet(u8),
+    ConcurrentRemove(u8),
+    ConcurrentReset,
+}
+
+/// Run `$body` with `$key_fn` bound to one of key closures chosen by `$slot`
+///
+/// Closures store values of different types and layouts.
+macro_rules! with_key {
+    ($slot:expr, |$key_fn:ident| $body:expr) => {
+        match $slot % 4 {
+            0 => {
+                let $key_fn = || Tracked::new(0_u8);
+                $body
+            }
+            1 => {
+                let $key_fn = || Tracked::new(1_u64);
+                $body
+            }
+            2 => {
+                let $key_fn = || Tracked::new([2_u32; 7]);
+                $body
+            }
+            _ => {
+                let $key_fn = || Tracked::new(String::from("three"));
+                $body
+            }
+        }
+    };
+}
+
+fuzz_target!(|ops: Vec<Op>| {
+    let allocated = ALLOCATED.load(Ordering::Relaxed);
+
+    let mut map = FnMap::new();
+    let mut concurrent = ConcurrentFnMap::new();
+
+    for &op in &ops {
+        match op {
+            Op::Get(slot) => with_key!(slot, |key_fn| {
+                let first = map.get(key_fn) as *const _;
+                assert_eq!(first, map.get(key_fn) as *const _);
+            }),
+
+            Op::GetMut(slot) => with_key!(slot, |key_fn| {
+                // drop stored value through mutable reference
+                let stored = map.get_mut(key_fn);
+                *stored = stored.clone();
+            }),
+
+            Op::Recompute(slot) => with_key!(slot, |key_fn| {
+                map.recompute(key_fn, |old| old.unwrap_or_else(key_fn));
+            }),
+
+            Op::Remove(slot) => with_key!(slot, |key_fn| {
+                map.remove(key_fn);
+            }),
+
+            Op::InvalidateType => map.invalidate_type::<Tracked<u8>>(),
+
+            Op::Clear => map.clear(),
+
+            Op::Reset => map.reset(),
+
+            Op::ConcurrentGet(slot) => with_key!(slot, |key_fn| {
+                let first = concurrent.get(key_fn) as *const _;
+                assert_eq!(first, concurrent.get(key_fn) as *const _);
+            }),
+
+            Op::ConcurrentRemove(slot) => with_key!(slot, |key_fn| {
+                concurrent.remove(key_fn);
+            }),
+
+            Op::ConcurrentReset => concurrent.reset(),
+        }
+    }
+
+    map.reset();
+    concurrent.reset();
+    assert_eq!(LIVE.load(Ordering::Relaxed), 0, "stored values are leaked");
+
+    drop(map);
+    drop(concurrent);
+    assert_eq!(
+        ALLOCATED.load(Ordering::Relaxed),
+        allocated,
+        "memory of maps is leaked"
+    );
+});
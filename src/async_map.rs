@@ -0,0 +1,388 @@
+use core::{
+    cell::UnsafeCell,
+    future::{poll_fn, Future},
+    mem,
+    ops::{Deref, DerefMut},
+    task::{Poll, Waker},
+};
+
+use alloc::vec::Vec;
+use parking_lot::Mutex;
+use type_key::TypeKey;
+
+use crate::raw::RawFnMap;
+
+#[derive(Debug, Default)]
+/// Thread safe FnMap implementation for async tasks
+///
+/// Tasks await the lock of the map instead of blocking their thread,
+/// and futures computing values are awaited outside the lock.
+/// ```
+/// use fn_map::async_map::AsyncConcurrentFnMap;
+///
+/// async fn load(map: &AsyncConcurrentFnMap) -> u32 {
+///     *map.get_async(|| async { 1 }).await
+/// }
+/// ```
+pub struct AsyncConcurrentFnMap {
+    raw: AsyncRwLock<RawFnMap>,
+}
+
+impl AsyncConcurrentFnMap {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Get or compute value using key, awaiting the future returned by `key_fn`
+    ///
+    /// Since the future is awaited without holding the lock, tasks missing same key at once all compute the value.
+    /// Only the first stored value is kept and returned to every tasks, while the others are dropped.
+    pub async fn get_async<T: 'static + Send + Sync, F: Future<Output = T>>(
+        &self,
+        key_fn: impl FnOnce() -> F,
+    ) -> &T {
+        let key = TypeKey::of_val(&key_fn);
+
+        let stored = self.raw.read().await.get::<T>(&key);
+        let ptr = match stored {
+            Some(ptr) => ptr,
+
+            None => {
+                let value = key_fn().await;
+
+                let mut raw = self.raw.write().await;
+                match raw.get::<T>(&key) {
+                    Some(ptr) => {
+                        // value may access the map while dropping
+                        drop(raw);
+                        drop(value);
+
+                        ptr
+                    }
+
+                    None => raw.insert(key, value),
+                }
+            }
+        };
+
+        // SAFETY: pointer is valid and reference cannot outlive more than Self
+        unsafe { ptr.as_ref() }
+    }
+
+    /// Reset stored values
+    pub fn reset(&mut self) {
+        self.raw.get_mut().reset();
+    }
+}
+
+#[derive(Debug, Default)]
+struct LockState {
+    readers: usize,
+    writing: bool,
+    // tasks waiting for the lock, woken on every release
+    waiters: Vec<Waker>,
+}
+
+#[derive(Debug, Default)]
+/// Reader writer lock whose tasks register their wakers instead of blocking
+///
+/// Mutex guarding its state is only held to update it, never while the lock is held.
+struct AsyncRwLock<T> {
+    state: Mutex<LockState>,
+    value: UnsafeCell<T>,
+}
+
+impl<T> AsyncRwLock<T> {
+    async fn read(&self) -> ReadGuard<'_, T> {
+        poll_fn(|cx| {
+            let mut state = self.state.lock();
+            if state.writing {
+                register(&mut state.waiters, cx.waker());
+                return Poll::Pending;
+            }
+
+            state.readers += 1;
+            Poll::Ready(ReadGuard(self))
+        })
+        .await
+    }
+
+    async fn write(&self) -> WriteGuard<'_, T> {
+        poll_fn(|cx| {
+            let mut state = self.state.lock();
+            if state.writing || state.readers > 0 {
+                register(&mut state.waiters, cx.waker());
+                return Poll::Pending;
+            }
+
+            state.writing = true;
+            Poll::Ready(WriteGuard(self))
+        })
+        .await
+    }
+
+    fn get_mut(&mut self) -> &mut T {
+        self.value.get_mut()
+    }
+
+    fn release(&self, f: impl FnOnce(&mut LockState)) {
+        let mut state = self.state.lock();
+        f(&mut state);
+        let waiters = mem::take(&mut state.waiters);
+        drop(state);
+
+        waiters.into_iter().for_each(Waker::wake);
+    }
+}
+
+// SAFETY: every values stored by AsyncConcurrentFnMap are Send and Sync
+#[cfg(not(feature = "no-unsafe-marker"))]
+unsafe impl Send for AsyncRwLock<RawFnMap> {}
+#[cfg(not(feature = "no-unsafe-marker"))]
+unsafe impl Sync for AsyncRwLock<RawFnMap> {}
+
+fn register(waiters: &mut Vec<Waker>, waker: &Waker) {
+    if !waiters.iter().any(|waiter| waiter.will_wake(waker)) {
+        waiters.push(waker.clone());
+    }
+}
+
+struct ReadGuard<'a, T>(&'a AsyncRwLock<T>);
+
+impl<T> Deref for ReadGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        // SAFETY: no writer holds the lock while read guard is alive
+        unsafe { &*self.0.value.get() }
+    }
+}
+
+impl<T> Drop for ReadGuard<'_, T> {
+    fn drop(&mut self) {
+        self.0.release(|state| state.readers -= 1);
+    }
+}
+
+struct WriteGuard<'a, T>(&'a AsyncRwLock<T>);
+
+impl<T> Deref for WriteGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        // SAFETY: write guard holds the lock exclusively
+        unsafe { &*self.0.value.get() }
+    }
+}
+
+impl<T> DerefMut for WriteGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        // SAFETY: write guard holds the lock exclusively
+        unsafe { &mut *self.0.value.get() }
+    }
+}
+
+impl<T> Drop for WriteGuard<'_, T> {
+    fn drop(&mut self) {
+        self.0.release(|state| state.writing = false);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use core::{
+        future::{poll_fn, Future},
+        pin::pin,
+        ptr,
+        sync::atomic::{AtomicBool, AtomicUsize, Ordering},
+        task::{Context, Poll, Waker},
+    };
+    use std::{
+        sync::Arc,
+        task::Wake,
+        thread::{self, Thread},
+    };
+
+    use super::{AsyncConcurrentFnMap, AsyncRwLock};
+
+    struct Unpark(Thread, AtomicUsize);
+
+    impl Wake for Unpark {
+        fn wake(self: Arc<Self>) {
+            self.wake_by_ref();
+        }
+
+        fn wake_by_ref(self: &Arc<Self>) {
+            self.1.fetch_add(1, Ordering::Relaxed);
+            self.0.unpark();
+        }
+    }
+
+    fn block_on<F: Future>(fut: F) -> F::Output {
+        let waker = Waker::from(Arc::new(Unpark(thread::current(), AtomicUsize::new(0))));
+        let mut cx = Context::from_waker(&waker);
+
+        let mut fut = pin!(fut);
+        loop {
+            if let Poll::Ready(output) = fut.as_mut().poll(&mut cx) {
+                return output;
+            }
+            thread::park();
+        }
+    }
+
+    /// Poll both futures on current task until both complete
+    async fn join<A: Future, B: Future>(a: A, b: B) -> (A::Output, B::Output) {
+        let (mut a, mut b) = (pin!(a), pin!(b));
+        let (mut a_out, mut b_out) = (None, None);
+
+        poll_fn(|cx| {
+            if a_out.is_none() {
+                if let Poll::Ready(out) = a.as_mut().poll(cx) {
+                    a_out = Some(out);
+                }
+            }
+            if b_out.is_none() {
+                if let Poll::Ready(out) = b.as_mut().poll(cx) {
+                    b_out = Some(out);
+                }
+            }
+
+            match (a_out.take(), b_out.take()) {
+                (Some(a), Some(b)) => Poll::Ready((a, b)),
+                (a, b) => {
+                    (a_out, b_out) = (a, b);
+                    Poll::Pending
+                }
+            }
+        })
+        .await
+    }
+
+    /// Yield to other futures until `flag` is set
+    async fn wait_for(flag: &AtomicBool) {
+        poll_fn(|cx| {
+            if flag.load(Ordering::Acquire) {
+                return Poll::Ready(());
+            }
+
+            cx.waker().wake_by_ref();
+            Poll::Pending
+        })
+        .await
+    }
+
+    #[test]
+    fn test_get_async() {
+        static COMPUTED: AtomicUsize = AtomicUsize::new(0);
+
+        let map = AsyncConcurrentFnMap::new();
+        let one = || async {
+            COMPUTED.fetch_add(1, Ordering::Relaxed);
+            1
+        };
+
+        assert_eq!(*block_on(map.get_async(one)), 1);
+        assert_eq!(*block_on(map.get_async(one)), 1);
+        assert_eq!(*block_on(map.get_async(|| async { 2 })), 2);
+        assert_eq!(COMPUTED.load(Ordering::Relaxed), 1);
+    }
+
+    #[test]
+    fn test_compute_outside_lock() {
+        static COMPUTED: AtomicBool = AtomicBool::new(false);
+
+        let map = AsyncConcurrentFnMap::new();
+
+        // first future waits for second one while computing, which blocking lock would deadlock
+        let (a, b) = block_on(join(
+            map.get_async(|| async {
+                wait_for(&COMPUTED).await;
+                1
+            }),
+            async {
+                let b = *map.get_async(|| async { 2 }).await;
+                COMPUTED.store(true, Ordering::Release);
+                b
+            },
+        ));
+        assert_eq!((*a, b), (1, 2));
+    }
+
+    #[test]
+    fn test_compute_twice() {
+        static STARTED: AtomicBool = AtomicBool::new(false);
+        static COMPUTED: AtomicUsize = AtomicUsize::new(0);
+
+        let map = AsyncConcurrentFnMap::new();
+        let value = || async {
+            wait_for(&STARTED).await;
+            COMPUTED.fetch_add(1, Ordering::Relaxed)
+        };
+
+        let (a, b) = block_on(join(map.get_async(value), async {
+            STARTED.store(true, Ordering::Release);
+            map.get_async(value).await
+        }));
+
+        // both tasks missed, but share the value stored first
+        assert_eq!(COMPUTED.load(Ordering::Relaxed), 2);
+        assert!(ptr::eq(a, b));
+    }
+
+    #[test]
+    fn test_lock_pending() {
+        let lock = AsyncRwLock::<u32>::default();
+        let unpark = Arc::new(Unpark(thread::current(), AtomicUsize::new(0)));
+        let waker = Waker::from(unpark.clone());
+        let mut cx = Context::from_waker(&waker);
+
+        let mut write = block_on(lock.write());
+        let mut read = pin!(lock.read());
+        assert!(read.as_mut().poll(&mut cx).is_pending());
+
+        *write = 1;
+        drop(write);
+        assert_eq!(unpark.1.load(Ordering::Relaxed), 1);
+
+        let Poll::Ready(guard) = read.as_mut().poll(&mut cx) else {
+            panic!("lock is released");
+        };
+        assert_eq!(*guard, 1);
+    }
+
+    #[cfg(not(feature = "no-unsafe-marker"))]
+    #[test]
+    fn test_future_send() {
+        fn is_send<T: Send>(_: &T) {}
+
+        // futures can be spawned on multi threaded executors
+        let map = AsyncConcurrentFnMap::new();
+        is_send(&map.get_async(|| async { 1 }));
+    }
+
+    #[cfg(not(feature = "no-unsafe-marker"))]
+    #[test]
+    fn test_threads() {
+        let map = AsyncConcurrentFnMap::new();
+        let one = || async { 1 };
+        let two = || async { 2 };
+
+        thread::scope(|scope| {
+            for _ in 0..4 {
+                scope.spawn(|| {
+                    for _ in 0..100 {
+                        assert_eq!(*block_on(map.get_async(one)), 1);
+                        assert_eq!(*block_on(map.get_async(two)), 2);
+                    }
+                });
+            }
+        });
+        assert!(ptr::eq(
+            block_on(map.get_async(one)),
+            block_on(map.get_async(one))
+        ));
+    }
+}
@@ -0,0 +1,263 @@
+use alloc::vec::Vec;
+#[cfg(feature = "stats")]
+use hashbrown::HashMap;
+use type_key::TypeKey;
+
+#[cfg(feature = "stats")]
+use crate::raw::KeyHasher;
+use crate::raw::RawFnMap;
+#[cfg(feature = "otel")]
+use crate::stats::Metric;
+#[cfg(feature = "stats")]
+use crate::stats::Stats;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+/// Policy choosing which value to drop when [`BoundedFnMap`] is full
+pub enum Eviction {
+    /// Drop least recently used value
+    #[default]
+    Lru,
+    /// Drop least frequently used value, least recently used one among ties
+    Lfu,
+}
+
+#[derive(Debug)]
+/// FnMap holding at most `capacity` values
+///
+/// Storing a new value while full drops one of stored values chosen by [`Eviction`] policy.
+/// Since values can be dropped on every access, it requires exclusive borrow.
+pub struct BoundedFnMap {
+    raw: RawFnMap,
+    capacity: usize,
+    eviction: Eviction,
+    tick: u64,
+
+    // misses of each key, kept after eviction
+    #[cfg(feature = "stats")]
+    misses: HashMap<TypeKey, u64, KeyHasher>,
+}
+
+impl BoundedFnMap {
+    /// Create map holding at most `capacity` values
+    ///
+    /// # Panics
+    /// Panics if `capacity` is zero
+    pub fn new(capacity: usize, eviction: Eviction) -> Self {
+        assert!(capacity > 0, "capacity must be greater than zero");
+
+        Self {
+            raw: RawFnMap::new(),
+            capacity,
+            eviction,
+            tick: 0,
+            #[cfg(feature = "stats")]
+            misses: HashMap::default(),
+        }
+    }
+
+    pub const fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    pub const fn eviction(&self) -> Eviction {
+        self.eviction
+    }
+
+    /// Number of stored values
+    pub fn len(&self) -> usize {
+        self.raw.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.raw.is_empty()
+    }
+
+    /// Get or compute value using key
+    ///
+    /// Value is computed before evicting, so a panicking `key_fn` leaves stored values untouched.
+    pub fn get<T: 'static + Send>(&mut self, key_fn: impl FnOnce() -> T) -> &T {
+        let key = TypeKey::of_val(&key_fn);
+        self.tick += 1;
+
+        let ptr = match self.raw.get::<T>(&key) {
+            Some(ptr) => {
+                self.raw.touch(&key, self.tick);
+                ptr
+            }
+
+            None => {
+                #[cfg(feature = "stats")]
+                {
+                    *self.misses.entry(key).or_default() += 1;
+                }
+
+                let value = key_fn();
+                if self.raw.len() >= self.capacity {
+                    self.evict();
+                }
+
+                let ptr = self.raw.insert(key, value);
+                self.raw.set_last_access(&key, self.tick);
+                ptr
+            }
+        };
+
+        // SAFETY: pointer is valid and reference cannot outlive more than Self
+        unsafe { ptr.as_ref() }
+    }
+
+    /// Keys of stored values in ascending order
+    ///
+    /// Unlike iteration order of the map, the order is reproducible for same set of keys.
+    pub fn dump_sorted(&self) -> Vec<TypeKey> {
+        self.raw.dump_sorted()
+    }
+
+    /// Number of hits to value stored using key
+    pub fn access_count<T: 'static>(&self, key_fn: impl FnOnce() -> T) -> Option<u64> {
+        self.raw.accesses(&TypeKey::of_val(&key_fn))
+    }
+
+    /// Keys computed more than `threshold` times, including recomputation after eviction
+    ///
+    /// Constantly recomputed keys indicate the capacity is too small.
+    #[cfg(feature = "stats")]
+    pub fn thrashing_keys(&self, threshold: u64) -> Vec<TypeKey> {
+        self.misses
+            .iter()
+            .filter(|(_, misses)| **misses > threshold)
+            .map(|(key, _)| *key)
+            .collect()
+    }
+
+    fn evict(&mut self) {
+        let victim = match self.eviction {
+            Eviction::Lru => self.raw.least_used_by(|_, last_access| last_access),
+            Eviction::Lfu => self
+                .raw
+                .least_used_by(|accesses, last_access| (accesses, last_access)),
+        };
+
+        if let Some(key) = victim {
+            self.raw.remove(&key);
+
+            #[cfg(feature = "stats")]
+            self.raw.count_eviction();
+        }
+    }
+
+    /// Hit, miss and eviction counts of the map
+    #[cfg(feature = "stats")]
+    pub fn stats(&self) -> Stats {
+        self.raw.stats()
+    }
+
+    /// Statistics, number of values and allocated bytes of the map as OpenTelemetry shaped metrics
+    #[cfg(feature = "otel")]
+    pub fn export_metrics(&self) -> Vec<Metric> {
+        self.raw.export_metrics()
+    }
+
+    /// Pass every metric of [`Self::export_metrics`] to `f`
+    ///
+    /// Meant to be called from callback of observable instruments registered with a meter.
+    #[cfg(feature = "otel")]
+    pub fn register_metrics(&self, f: impl FnMut(&Metric)) {
+        self.export_metrics().iter().for_each(f);
+    }
+
+    /// Drop every stored values and deallocate memory
+    pub fn reset(&mut self) {
+        self.raw.reset();
+    }
+}
+
+// SAFETY: every values stored are Send
+#[cfg(not(feature = "no-unsafe-marker"))]
+unsafe impl Send for BoundedFnMap {}
+
+#[cfg(test)]
+mod tests {
+    use super::{BoundedFnMap, Eviction};
+
+    #[test]
+    fn test_lfu() {
+        let mut map = BoundedFnMap::new(2, Eviction::Lfu);
+
+        let hot = || 1;
+        let cold = || 2;
+        let new = || 3;
+
+        map.get(hot);
+        map.get(cold);
+        for _ in 0..3 {
+            map.get(hot);
+        }
+        // cold is most recently used but least frequently used
+        map.get(cold);
+
+        assert_eq!(map.access_count(hot), Some(3));
+        assert_eq!(map.access_count(cold), Some(1));
+
+        assert_eq!(*map.get(new), 3);
+        assert_eq!(map.len(), 2);
+        assert_eq!(map.access_count(hot), Some(3));
+        assert_eq!(map.access_count(cold), None);
+    }
+
+    #[test]
+    fn test_lru() {
+        let mut map = BoundedFnMap::new(2, Eviction::Lru);
+
+        let a = || 1;
+        let b = || 2;
+
+        map.get(a);
+        map.get(a);
+        map.get(b);
+        assert_eq!(*map.get(|| 3), 3);
+
+        assert_eq!(map.access_count(a), None);
+        assert_eq!(map.access_count(b), Some(0));
+    }
+
+    #[cfg(feature = "stats")]
+    #[test]
+    fn test_thrashing() {
+        use type_key::TypeKey;
+
+        let mut map = BoundedFnMap::new(1, Eviction::Lru);
+
+        let a = || 1;
+        let b = || 2;
+
+        for _ in 0..3 {
+            map.get(a);
+            map.get(b);
+        }
+        assert!(map.thrashing_keys(3).is_empty());
+
+        map.get(a);
+        assert_eq!(map.thrashing_keys(3), [TypeKey::of_val(&a)]);
+
+        let mut keys = map.thrashing_keys(2);
+        keys.sort();
+        let mut expected = [TypeKey::of_val(&a), TypeKey::of_val(&b)];
+        expected.sort();
+        assert_eq!(keys, expected);
+    }
+
+    #[cfg(feature = "stats")]
+    #[test]
+    fn test_stats() {
+        let mut map = BoundedFnMap::new(1, Eviction::Lru);
+
+        let a = || 1;
+        map.get(a);
+        map.get(a);
+        map.get(|| 2);
+
+        let stats = map.stats();
+        assert_eq!((stats.hits, stats.misses, stats.evictions), (1, 2, 1));
+    }
+}
@@ -0,0 +1,71 @@
+use core::sync::atomic::{AtomicU64, Ordering};
+#[cfg(feature = "std")]
+use std::time::Instant;
+
+/// Source of current time used by time based gets
+///
+/// Time is measured in ticks whose unit is defined by the clock.
+/// It must never go backwards.
+pub trait Clock {
+    fn now(&self) -> u64;
+}
+
+#[cfg(feature = "std")]
+#[derive(Debug, Clone, Copy)]
+/// Clock counting milliseconds elapsed since it is created
+pub struct SystemClock {
+    origin: Instant,
+}
+
+#[cfg(feature = "std")]
+impl SystemClock {
+    pub fn new() -> Self {
+        Self {
+            origin: Instant::now(),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl Default for SystemClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(feature = "std")]
+impl Clock for SystemClock {
+    fn now(&self) -> u64 {
+        self.origin.elapsed().as_millis() as u64
+    }
+}
+
+#[derive(Debug, Default)]
+/// Clock advancing only when told to
+///
+/// Useful for testing time based behavior deterministically, or driving time by ticks without std.
+/// ```
+/// use fn_map::clock::{Clock, ManualClock};
+///
+/// let clock = ManualClock::new();
+/// clock.advance(5);
+/// assert_eq!(clock.now(), 5);
+/// ```
+pub struct ManualClock(AtomicU64);
+
+impl ManualClock {
+    pub const fn new() -> Self {
+        Self(AtomicU64::new(0))
+    }
+
+    /// Move the clock forward by `ticks`
+    pub fn advance(&self, ticks: u64) {
+        self.0.fetch_add(ticks, Ordering::Relaxed);
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> u64 {
+        self.0.load(Ordering::Relaxed)
+    }
+}
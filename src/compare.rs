@@ -0,0 +1,64 @@
+use core::ptr::NonNull;
+
+use alloc::vec::Vec;
+use type_key::TypeKey;
+
+type EqFn = unsafe fn(NonNull<()>, NonNull<()>) -> bool;
+
+#[derive(Debug, Default)]
+/// Registry of types whose values can be compared by [`FnMap::values_equal`]
+///
+/// Values of unregistered types are only compared by their keys.
+/// ```
+/// use fn_map::{compare::EqRegistry, FnMap};
+///
+/// let mut registry = EqRegistry::new();
+/// registry.register::<i32>();
+///
+/// let value = |n| move || n;
+///
+/// let a = FnMap::new();
+/// let b = FnMap::new();
+/// a.get(value(1));
+/// b.get(value(2));
+///
+/// assert!(a.keys_equal(&b));
+/// assert!(!a.values_equal(&b, &registry));
+/// ```
+///
+/// [`FnMap::values_equal`]: crate::FnMap::values_equal
+pub struct EqRegistry(Vec<(TypeKey, EqFn)>);
+
+impl EqRegistry {
+    pub const fn new() -> Self {
+        Self(Vec::new())
+    }
+
+    /// Compare values of type `T` using its [`PartialEq`] implementation
+    pub fn register<T: 'static + PartialEq>(&mut self) {
+        let ty = TypeKey::of::<T>();
+
+        if !self.0.iter().any(|(registered, _)| *registered == ty) {
+            self.0.push((ty, eq_value::<T>));
+        }
+    }
+
+    /// Compare values of type `ty` pointed by `a` and `b`
+    ///
+    /// Returns `None` if `ty` is not registered.
+    ///
+    /// # Safety
+    /// `a` and `b` must point to valid values of type `ty`
+    pub(crate) unsafe fn eq(&self, ty: &TypeKey, a: NonNull<()>, b: NonNull<()>) -> Option<bool> {
+        let (_, eq) = self.0.iter().find(|(registered, _)| registered == ty)?;
+
+        // SAFETY: guaranteed by caller
+        Some(unsafe { eq(a, b) })
+    }
+}
+
+/// compare values of type `T` pointed by `a` and `b`
+unsafe fn eq_value<T: PartialEq>(a: NonNull<()>, b: NonNull<()>) -> bool {
+    // SAFETY: guaranteed by caller
+    unsafe { a.cast::<T>().as_ref() == b.cast::<T>().as_ref() }
+}
@@ -0,0 +1,137 @@
+//! Byte oriented LZ77 codec for blobs stored by [`FnMap::get_compressed`]
+//!
+//! Compressed stream is a sequence of tokens.
+//! Token below `0x80` is followed by `token + 1` literal bytes,
+//! and other tokens copy `(token & 0x7f) + MIN_MATCH` bytes starting at little endian `u16` offset back from the end of output.
+//!
+//! [`FnMap::get_compressed`]: crate::FnMap::get_compressed
+
+use alloc::{vec, vec::Vec};
+
+const MIN_MATCH: usize = 4;
+const MAX_MATCH: usize = MIN_MATCH + 0x7f;
+const MAX_LITERALS: usize = 0x80;
+const WINDOW: usize = u16::MAX as usize;
+const HASH_BITS: u32 = 12;
+
+fn hash(bytes: &[u8]) -> usize {
+    let word = u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+    (word.wrapping_mul(0x9e37_79b1) >> (32 - HASH_BITS)) as usize
+}
+
+fn push_literals(out: &mut Vec<u8>, literals: &[u8]) {
+    for chunk in literals.chunks(MAX_LITERALS) {
+        out.push((chunk.len() - 1) as u8);
+        out.extend_from_slice(chunk);
+    }
+}
+
+/// Compress `input`, replacing repeated sequences with references to their previous occurrence
+pub(crate) fn compress(input: &[u8]) -> Vec<u8> {
+    let mut out = Vec::new();
+    // last position of each hashed 4 bytes sequence
+    let mut table = vec![usize::MAX; 1 << HASH_BITS];
+
+    let mut literals = 0;
+    let mut i = 0;
+    while i + MIN_MATCH <= input.len() {
+        let candidate = core::mem::replace(&mut table[hash(&input[i..])], i);
+
+        if candidate == usize::MAX
+            || i - candidate > WINDOW
+            || input[candidate..candidate + MIN_MATCH] != input[i..i + MIN_MATCH]
+        {
+            i += 1;
+            continue;
+        }
+
+        // match may overlap current position, which repeats the sequence
+        let len = input[candidate..]
+            .iter()
+            .zip(&input[i..])
+            .take(MAX_MATCH)
+            .take_while(|(a, b)| a == b)
+            .count();
+
+        push_literals(&mut out, &input[literals..i]);
+        out.push(0x80 | (len - MIN_MATCH) as u8);
+        out.extend_from_slice(&((i - candidate) as u16).to_le_bytes());
+
+        i += len;
+        literals = i;
+    }
+    push_literals(&mut out, &input[literals..]);
+
+    out
+}
+
+/// Decompress `input` produced by [`compress`] into `len` bytes
+pub(crate) fn decompress(input: &[u8], len: usize) -> Vec<u8> {
+    let mut out = Vec::with_capacity(len);
+
+    let mut i = 0;
+    while i < input.len() {
+        let token = input[i] as usize;
+        i += 1;
+
+        if token < 0x80 {
+            let literals = token + 1;
+            out.extend_from_slice(&input[i..i + literals]);
+            i += literals;
+        } else {
+            let offset = u16::from_le_bytes([input[i], input[i + 1]]) as usize;
+            i += 2;
+
+            let start = out.len() - offset;
+            for j in start..start + (token & 0x7f) + MIN_MATCH {
+                out.push(out[j]);
+            }
+        }
+    }
+
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec::Vec;
+
+    use super::{compress, decompress};
+
+    fn round_trip(input: &[u8]) -> usize {
+        let compressed = compress(input);
+        assert_eq!(decompress(&compressed, input.len()), input);
+
+        compressed.len()
+    }
+
+    #[test]
+    fn test_round_trip() {
+        round_trip(&[]);
+        round_trip(b"abc");
+        round_trip(b"abcdabcd");
+        round_trip(&[7; 1000]);
+
+        // pseudo random bytes hardly repeat
+        let mut state = 1_u32;
+        let noise: Vec<u8> = (0..10_000)
+            .map(|_| {
+                state = state.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+                (state >> 16) as u8
+            })
+            .collect();
+        assert!(round_trip(&noise) <= noise.len() + noise.len() / 100 + 1);
+    }
+
+    #[test]
+    fn test_repetitive() {
+        let input: Vec<u8> = b"fn-map compresses repeated text. "
+            .iter()
+            .copied()
+            .cycle()
+            .take(100_000)
+            .collect();
+
+        assert!(round_trip(&input) < input.len() / 20);
+    }
+}
@@ -0,0 +1,34 @@
+use core::cell::RefCell;
+use std::vec::Vec;
+
+use type_key::TypeKey;
+
+std::thread_local! {
+    // keys being computed in current thread with their map
+    static COMPUTING: RefCell<Vec<(*const (), TypeKey)>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Key being computed in current thread by `map`
+pub(crate) fn current(map: *const ()) -> Option<TypeKey> {
+    COMPUTING.with_borrow(|computing| match computing.last() {
+        Some(&(computing_map, key)) if computing_map == map => Some(key),
+        _ => None,
+    })
+}
+
+/// Mark key as being computed by map until dropped
+pub(crate) struct Computing(());
+
+impl Computing {
+    pub fn enter(map: *const (), key: TypeKey) -> Self {
+        COMPUTING.with_borrow_mut(|computing| computing.push((map, key)));
+
+        Self(())
+    }
+}
+
+impl Drop for Computing {
+    fn drop(&mut self) {
+        COMPUTING.with_borrow_mut(|computing| computing.pop());
+    }
+}
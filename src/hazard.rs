@@ -0,0 +1,209 @@
+//! Snapshots of stored values read without locking, protected by hazard pointers
+//!
+//! Each thread owns one hazard slot from a global list for its lifetime.
+//! Readers publish snapshot they read in their slot, and writers free a replaced snapshot
+//! only after no slot holds it.
+
+use core::{
+    ptr::{self, NonNull},
+    sync::atomic::{AtomicBool, AtomicPtr, Ordering},
+};
+use std::{boxed::Box, vec::Vec};
+
+use hashbrown::HashMap;
+use parking_lot::Mutex;
+use type_key::TypeKey;
+
+use crate::raw::{KeyHasher, RawFnMap};
+
+/// Pointers and epochs of stored values at some point
+#[derive(Debug, Default, Clone)]
+pub(crate) struct Snapshot(HashMap<TypeKey, (NonNull<()>, u64), KeyHasher>);
+
+impl Snapshot {
+    /// Snapshot of every stored values of `raw`
+    fn of(raw: &RawFnMap) -> Self {
+        Self(
+            raw.keys()
+                .filter_map(|key| Some((key, (raw.entry(&key)?.0, raw.epoch(&key)?))))
+                .collect(),
+        )
+    }
+
+    /// Pointer and epoch of value stored using key
+    #[inline]
+    pub fn get(&self, key: &TypeKey) -> Option<(NonNull<()>, u64)> {
+        self.0.get(key).copied()
+    }
+}
+
+/// Latest snapshot of a map and replaced snapshots possibly still being read
+#[derive(Debug, Default)]
+pub(crate) struct SharedSnapshot {
+    current: AtomicPtr<Snapshot>,
+    retired: Mutex<Vec<NonNull<Snapshot>>>,
+}
+
+impl SharedSnapshot {
+    /// Read latest snapshot without locking
+    ///
+    /// Returns `None` if nothing is published yet or hazard slot of current thread is destroyed.
+    #[inline]
+    pub fn read<R>(&self, f: impl FnOnce(&Snapshot) -> R) -> Option<R> {
+        LOCAL
+            .try_with(|local| {
+                let slot = local.0;
+
+                let mut ptr = self.current.load(Ordering::Acquire);
+                loop {
+                    if ptr.is_null() {
+                        return None;
+                    }
+
+                    slot.hazard.store(ptr, Ordering::SeqCst);
+                    let current = self.current.load(Ordering::SeqCst);
+                    if current == ptr {
+                        break;
+                    }
+                    ptr = current;
+                }
+
+                // SAFETY: snapshot was still published after hazard is set, so writers see hazard before freeing it
+                let res = f(unsafe { &*ptr });
+                slot.hazard.store(ptr::null_mut(), Ordering::Release);
+
+                Some(res)
+            })
+            .ok()
+            .flatten()
+    }
+
+    /// Publish latest snapshot with value stored using key inserted
+    ///
+    /// # Safety
+    /// Must not be called by multiple threads at once.
+    pub unsafe fn insert(&self, key: TypeKey, ptr: NonNull<()>, epoch: u64) {
+        let current = self.current.load(Ordering::Acquire);
+        // SAFETY: only caller replaces and frees snapshots
+        let mut snapshot = unsafe { current.as_ref() }.cloned().unwrap_or_default();
+        snapshot.0.insert(key, (ptr, epoch));
+
+        let old = self
+            .current
+            .swap(Box::into_raw(Box::new(snapshot)), Ordering::SeqCst);
+
+        let mut retired = self.retired.lock();
+        if let Some(old) = NonNull::new(old) {
+            retired.push(old);
+        }
+
+        let hazards = hazards();
+        retired.retain(|&snapshot| {
+            if hazards.contains(&snapshot.as_ptr()) {
+                return true;
+            }
+
+            // SAFETY: snapshot is not published and no reader protects it
+            drop(unsafe { Box::from_raw(snapshot.as_ptr()) });
+            false
+        });
+    }
+
+    /// Replace every snapshots with snapshot of `raw`
+    pub fn rebuild(&mut self, raw: &RawFnMap) {
+        self.free();
+        if !raw.is_empty() {
+            *self.current.get_mut() = Box::into_raw(Box::new(Snapshot::of(raw)));
+        }
+    }
+
+    fn free(&mut self) {
+        let current = NonNull::new(*self.current.get_mut());
+        *self.current.get_mut() = ptr::null_mut();
+
+        for snapshot in current.into_iter().chain(self.retired.get_mut().drain(..)) {
+            // SAFETY: no reader can borrow snapshots when exclusively borrowed
+            drop(unsafe { Box::from_raw(snapshot.as_ptr()) });
+        }
+    }
+}
+
+impl Drop for SharedSnapshot {
+    fn drop(&mut self) {
+        self.free();
+    }
+}
+
+/// Hazard slot owned by a thread
+struct Slot {
+    hazard: AtomicPtr<Snapshot>,
+    used: AtomicBool,
+    next: *const Slot,
+}
+
+// slots are only prepended and never freed
+static SLOTS: AtomicPtr<Slot> = AtomicPtr::new(ptr::null_mut());
+
+/// Iterate every slots ever allocated
+fn slots() -> impl Iterator<Item = &'static Slot> {
+    // SAFETY: slots are leaked
+    let mut next = unsafe { SLOTS.load(Ordering::Acquire).as_ref() };
+
+    core::iter::from_fn(move || {
+        let slot = next?;
+        // SAFETY: slots are leaked
+        next = unsafe { slot.next.as_ref() };
+        Some(slot)
+    })
+}
+
+/// Snapshots being read by any thread
+fn hazards() -> Vec<*mut Snapshot> {
+    slots()
+        .map(|slot| slot.hazard.load(Ordering::SeqCst))
+        .filter(|ptr| !ptr.is_null())
+        .collect()
+}
+
+/// Slot of current thread, released on thread exit
+struct LocalSlot(&'static Slot);
+
+impl LocalSlot {
+    fn acquire() -> Self {
+        if let Some(slot) = slots().find(|slot| {
+            slot.used
+                .compare_exchange(false, true, Ordering::Acquire, Ordering::Relaxed)
+                .is_ok()
+        }) {
+            return Self(slot);
+        }
+
+        let slot = Box::into_raw(Box::new(Slot {
+            hazard: AtomicPtr::new(ptr::null_mut()),
+            used: AtomicBool::new(true),
+            next: ptr::null(),
+        }));
+
+        let mut head = SLOTS.load(Ordering::Acquire);
+        loop {
+            // SAFETY: slot is not shared until it is prepended
+            unsafe { (*slot).next = head };
+            match SLOTS.compare_exchange_weak(head, slot, Ordering::AcqRel, Ordering::Acquire) {
+                // SAFETY: slot is leaked
+                Ok(_) => break Self(unsafe { &*slot }),
+                Err(current) => head = current,
+            }
+        }
+    }
+}
+
+impl Drop for LocalSlot {
+    fn drop(&mut self) {
+        self.0.hazard.store(ptr::null_mut(), Ordering::Release);
+        self.0.used.store(false, Ordering::Release);
+    }
+}
+
+std::thread_local! {
+    static LOCAL: LocalSlot = LocalSlot::acquire();
+}
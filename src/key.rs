@@ -0,0 +1,108 @@
+use core::{
+    fmt::Debug,
+    hash::{BuildHasher, BuildHasherDefault, Hash},
+    mem,
+};
+
+use type_key::TypeKey;
+
+use crate::raw::{KeyHasher, WideHasher};
+
+/// Strategy deriving keys of [`FnMap`] from key closures
+///
+/// # Safety
+/// Key derived from a closure must determine output type of the closure,
+/// so closures of different output types never share a key.
+///
+/// [`FnMap`]: crate::FnMap
+pub unsafe trait KeyStrategy {
+    type Key: 'static + Copy + Eq + Hash + Debug;
+    type Hasher: BuildHasher + Default + Debug;
+
+    fn key<T, F: FnOnce() -> T>(key_fn: &F) -> Self::Key;
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+/// Key closures using type of the closure
+pub struct TypeIdentity;
+
+// SAFETY: closure type determines its output type
+unsafe impl KeyStrategy for TypeIdentity {
+    type Key = TypeKey;
+    type Hasher = KeyHasher;
+
+    #[inline]
+    fn key<T, F: FnOnce() -> T>(key_fn: &F) -> TypeKey {
+        TypeKey::of_val(key_fn)
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+/// Key closures using type of the closure and address of function pointers
+///
+/// Different functions coerced to same function pointer type have different keys.
+/// ```
+/// use fn_map::{key::FnPointer, FnMap};
+///
+/// fn one() -> i32 {
+///     1
+/// }
+/// fn two() -> i32 {
+///     2
+/// }
+///
+/// let map = FnMap::<FnPointer>::default();
+/// assert_eq!(*map.get(one as fn() -> i32), 1);
+/// assert_eq!(*map.get(two as fn() -> i32), 2);
+/// ```
+pub struct FnPointer;
+
+// SAFETY: closure type determines its output type
+unsafe impl KeyStrategy for FnPointer {
+    type Key = (TypeKey, usize);
+    type Hasher = BuildHasherDefault<WideHasher>;
+
+    #[inline]
+    fn key<T, F: FnOnce() -> T>(key_fn: &F) -> (TypeKey, usize) {
+        let address = if TypeKey::of::<F>() == TypeKey::of::<fn() -> T>() {
+            // SAFETY: F is function pointer type
+            unsafe { mem::transmute_copy::<F, fn() -> T>(key_fn) as usize }
+        } else {
+            0
+        };
+
+        (TypeKey::of_val(key_fn), address)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::FnMap;
+
+    use super::FnPointer;
+
+    #[test]
+    fn test_fn_pointer() {
+        fn one() -> i32 {
+            1
+        }
+        fn two() -> i32 {
+            2
+        }
+
+        let map = FnMap::<FnPointer>::default();
+        let one: fn() -> i32 = one;
+        let two: fn() -> i32 = two;
+
+        assert_eq!(*map.get(one), 1);
+        assert_eq!(*map.get(two), 2);
+        assert_eq!(*map.get(one), 1);
+
+        // closures are still keyed by their type
+        assert_eq!(*map.get(|| 3), 3);
+
+        let default = FnMap::new();
+        assert_eq!(*default.get(one), 1);
+        assert_eq!(*default.get(two), 1);
+    }
+}
@@ -1,222 +1,4336 @@
 #![no_std]
 #![doc = include_str!("../README.md")]
 
+extern crate alloc;
+#[cfg(feature = "std")]
+extern crate std;
+
+#[cfg(feature = "async")]
+pub mod async_map;
+pub mod bounded;
+pub mod clock;
+pub mod compare;
+#[cfg(feature = "compress")]
+mod compress;
+#[cfg(feature = "deps")]
+mod deps;
+#[cfg(feature = "lock-free-read")]
+mod hazard;
+pub mod key;
+pub mod loader;
+#[cfg(all(feature = "mmap", unix))]
+pub mod persistent;
 pub mod raw;
+pub mod read_only;
+pub mod scoped;
+pub mod spmc;
+#[cfg(feature = "stats")]
+pub mod stats;
+pub mod tiered;
 
-use core::{cell::UnsafeCell, ptr::NonNull};
-use parking_lot::RwLock;
+use alloc::{borrow::Cow, boxed::Box, sync::Arc, vec::Vec};
+#[cfg(feature = "compress")]
+use core::alloc::Layout;
+use core::any::Any;
+#[cfg(all(feature = "stats", feature = "std"))]
+use core::any::TypeId;
+#[cfg(feature = "std")]
+use core::time::Duration;
+use core::{
+    any,
+    cell::{Cell, RefCell, UnsafeCell},
+    convert::Infallible,
+    fmt,
+    hash::{BuildHasher, Hash},
+    marker::PhantomData,
+    mem::MaybeUninit,
+    ops::Deref,
+    pin::Pin,
+    ptr::NonNull,
+    slice,
+    sync::atomic::{AtomicU64, Ordering},
+};
+use hashbrown::{hash_map::DefaultHashBuilder, HashMap};
+use nohash_hasher::BuildNoHashHasher;
+#[cfg(feature = "std")]
+use parking_lot::{Condvar, Mutex};
+use parking_lot::{RwLock, RwLockReadGuard, RwLockWriteGuard};
+#[cfg(all(feature = "stats", feature = "std"))]
+use std::time::Instant;
 use type_key::TypeKey;
 
-use crate::raw::RawFnMap;
+use crate::{
+    clock::Clock,
+    compare::EqRegistry,
+    key::{KeyStrategy, TypeIdentity},
+    loader::Loader,
+    raw::RawFnMap,
+    read_only::ReadOnly,
+};
 
-#[derive(Debug, Default)]
+#[cfg(all(feature = "stats", feature = "std"))]
+use crate::stats::LockStats;
+#[cfg(feature = "otel")]
+use crate::stats::Metric;
+#[cfg(feature = "stats")]
+use crate::stats::{EntryStats, Stats};
+
+/// Key of closure `F` folded with discriminant `N`
+fn variant_key<const N: usize, F>(_: &F) -> TypeKey {
+    struct Variant<const N: usize, F>(PhantomData<F>);
+
+    TypeKey::of::<Variant<N, F>>()
+}
+
+/// Key of closure `F` in namespace `NS`
+fn ns_key<const NS: u64, F>(_: &F) -> TypeKey {
+    struct Namespaced<const NS: u64, F>(PhantomData<F>);
+
+    TypeKey::of::<Namespaced<NS, F>>()
+}
+
+/// Key of closure `F` used for values stored in [`Arc`]
+fn arc_key<F>(_: &F) -> TypeKey {
+    struct Shared<F>(PhantomData<F>);
+
+    TypeKey::of::<Shared<F>>()
+}
+
+/// Key of closure `F` used for values keyed by runtime key `K` hashed with `H`
+fn keyed_key<H, K, F>(_: &F) -> TypeKey {
+    struct Keyed<H, K, F>(PhantomData<(H, K, F)>);
+
+    TypeKey::of::<Keyed<H, K, F>>()
+}
+
+/// Key of field extracted by closure `E` from value of closure `F`
+fn field_key<F, E>(_: &F, _: &E) -> TypeKey {
+    struct Field<F, E>(PhantomData<(F, E)>);
+
+    TypeKey::of::<Field<F, E>>()
+}
+
+/// Key of closure `F` used for over-aligned values
+fn aligned_key<F>(_: &F) -> TypeKey {
+    struct Aligned<F>(PhantomData<F>);
+
+    TypeKey::of::<Aligned<F>>()
+}
+
+/// Key of closure `F` used for values stored with retry timestamp
+fn retry_key<F>(_: &F) -> TypeKey {
+    struct Retry<F>(PhantomData<F>);
+
+    TypeKey::of::<Retry<F>>()
+}
+
+/// Key of closure `F` used for values stored with expiration timestamp
+fn ttl_key<F>(_: &F) -> TypeKey {
+    struct Ttl<F>(PhantomData<F>);
+
+    TypeKey::of::<Ttl<F>>()
+}
+
+/// Key of closure `F` used for compressed byte buffers
+#[cfg(feature = "compress")]
+fn compressed_key<F>(_: &F) -> TypeKey {
+    struct Compressed<F>(PhantomData<F>);
+
+    TypeKey::of::<Compressed<F>>()
+}
+
+#[derive(Debug)]
 /// Single thread only FnMap implementation.
 ///
 /// This implementation is zero cost.
-pub struct FnMap(UnsafeCell<RawFnMap>);
+/// Keys are derived from key closures using [`KeyStrategy`] `S`.
+pub struct FnMap<S: KeyStrategy = TypeIdentity> {
+    raw: UnsafeCell<RawFnMap<S::Hasher, S::Key>>,
+
+    // last accessed key and value
+    last: Cell<Option<(S::Key, NonNull<()>)>>,
+
+    // number and generation of hits of last accessed value not counted in raw map yet
+    #[cfg(feature = "stats")]
+    last_hits: Cell<(u64, u64)>,
+
+    // maximum and spent misses of budgeted gets since last reset
+    budget: Option<usize>,
+    misses: Cell<usize>,
+
+    // maximum and counted gets before stored values are retired, and if any are retired since last reset
+    auto_reset: Option<u64>,
+    accesses: Cell<u64>,
+    auto_retired: Cell<bool>,
+
+    #[cfg(feature = "debug-keys")]
+    on_miss: MissHook,
+
+    // closures and their arguments being computed by get_recursive
+    recursing: RefCell<Vec<(TypeKey, Box<dyn Any + Send>)>>,
+}
 
 impl FnMap {
     #[inline]
     pub fn new() -> Self {
         Self::default()
     }
+}
+
+impl<S: KeyStrategy> Default for FnMap<S> {
+    fn default() -> Self {
+        Self {
+            raw: UnsafeCell::default(),
+            last: Cell::new(None),
+            #[cfg(feature = "stats")]
+            last_hits: Cell::new((0, 0)),
+            budget: None,
+            misses: Cell::new(0),
+            auto_reset: None,
+            accesses: Cell::new(0),
+            auto_retired: Cell::new(false),
+            #[cfg(feature = "debug-keys")]
+            on_miss: MissHook::default(),
+            recursing: RefCell::default(),
+        }
+    }
+}
 
+impl<S: KeyStrategy> FnMap<S> {
+    /// Label map with `name` to distinguish it from other maps
+    #[inline]
+    pub fn with_name(mut self, name: &'static str) -> Self {
+        self.raw.get_mut().set_name(name);
+        self
+    }
+    /// Label of the map
+    #[inline]
+    pub fn name(&self) -> Option<&'static str> {
+        // SAFETY: name is only changed with exclusive borrow
+        unsafe { &*self.raw.get().cast_const() }.name()
+    }
+    /// Limit misses of [`Self::get_budgeted`] to `budget` between resets
+    #[inline]
+    pub fn with_compute_budget(mut self, budget: usize) -> Self {
+        self.budget = Some(budget);
+        self
+    }
+    /// Recompute every values after `accesses` gets
+    ///
+    /// Next get after `accesses` gets removes every stored values before looking up.
+    /// Values removed by shared gets like [`Self::get`] can be still borrowed,
+    /// so they stay allocated until [`Self::reset`] or [`Self::maintain`] and memory of the map grows on every automatic reset until then.
+    /// [`Self::get_mut`] resets the map instead, dropping them.
+    #[inline]
+    pub fn with_auto_reset_after(mut self, accesses: u64) -> Self {
+        self.auto_reset = Some(accesses);
+        self
+    }
+    /// Gets counted since the map is reset
+    #[inline]
+    pub fn accesses(&self) -> u64 {
+        self.accesses.get()
+    }
+    /// Reset the map if automatic reset is due or has retired values on shared gets
+    ///
+    /// Values removed by automatic reset of [`Self::get`] are freed, bounding memory of the map.
+    /// Every stored values are recomputed on next access.
+    #[inline]
+    pub fn maintain(&mut self) {
+        if self.auto_reset_due() || self.auto_retired.get() {
+            self.reset();
+        }
+    }
+    fn auto_reset_due(&self) -> bool {
+        self.auto_reset
+            .is_some_and(|limit| self.accesses.get() >= limit)
+    }
+    /// Call `f` with type name and key of every closure computing a value of the map
+    ///
+    /// Calls are made before closures run, in order of computation.
+    #[cfg(feature = "debug-keys")]
+    #[inline]
+    pub fn set_on_miss(&mut self, f: impl FnMut(&'static str, TypeKey) + Send + 'static) {
+        self.on_miss.0.set(Some(Box::new(f)));
+    }
+    /// Misses of [`Self::get_budgeted`] left before next reset
+    ///
+    /// Returns `None` if the map has no compute budget.
+    #[inline]
+    pub fn remaining_budget(&self) -> Option<usize> {
+        Some(self.budget?.saturating_sub(self.misses.get()))
+    }
     #[inline]
     pub fn get_ptr<T: 'static + Send>(&self, key_fn: impl FnOnce() -> T) -> NonNull<T> {
-        let key = TypeKey::of_val(&key_fn);
+        // SAFETY: key strategy guarantees key determines type of the value
+        unsafe { self.get_ptr_by_key(S::key(&key_fn), key_fn) }
+    }
+    /// Get or compute value using explicit key
+    ///
+    /// # Safety
+    /// Every value stored using `key` must be type of `T`
+    #[inline]
+    pub unsafe fn get_ptr_by_key<T: 'static + Send>(
+        &self,
+        key: S::Key,
+        init: impl FnOnce() -> T,
+    ) -> NonNull<T> {
+        if self.auto_reset.is_some() {
+            if self.auto_reset_due() {
+                self.accesses.set(0);
+                self.auto_retired.set(true);
+                self.take_last();
+
+                // SAFETY: safe to borrow exclusively since no one can borrow more
+                unsafe { &mut *self.raw.get() }.retire_all();
+            }
+
+            self.accesses.set(self.accesses.get() + 1);
+        }
+
+        // dependencies are only recorded between type keys
+        #[cfg(feature = "deps")]
+        let type_key = (&key as &dyn Any).downcast_ref::<TypeKey>().copied();
+
+        #[cfg(feature = "deps")]
+        if let (Some(dependent), Some(key)) = (deps::current(self.id()), type_key) {
+            // SAFETY: safe to borrow exclusively since no one can borrow more
+            unsafe { &mut *self.raw.get() }.add_dependency(dependent, key);
+        }
 
         // SAFETY: safe to borrow shared because self is borrowed shared
-        if let Some(ptr) = unsafe { &*self.0.get().cast_const() }.get(&key) {
-            return ptr;
+        let raw = unsafe { &*self.raw.get().cast_const() };
+
+        match self.last.get() {
+            Some((last, ptr)) if last == key => {
+                // counted without lookup until last accessed value changes
+                #[cfg(feature = "stats")]
+                self.last_hits
+                    .set((self.last_hits.get().0 + 1, raw.generation()));
+
+                return ptr.cast();
+            }
+
+            _ => {}
         }
 
-        // accuire value first before borrowing exclusively
-        let value = key_fn();
+        let ptr = match raw.get(&key) {
+            Some(ptr) => ptr,
 
-        // SAFETY: safe to borrow exclusively since no one can borrow more
-        unsafe { &mut *self.0.get() }.insert(key, value)
-    }
+            None => {
+                #[cfg(feature = "debug-keys")]
+                self.on_miss
+                    .call(any::type_name_of_val(&init), TypeKey::of_val(&init));
+
+                #[cfg(feature = "deps")]
+                let _computing = type_key.map(|key| deps::Computing::enter(self.id(), key));
+
+                #[cfg(all(feature = "stats", feature = "std"))]
+                let started = Instant::now();
 
+                // accuire value first before borrowing exclusively
+                let value = init();
+
+                // SAFETY: safe to borrow exclusively since no one can borrow more
+                let raw = unsafe { &mut *self.raw.get() };
+
+                #[cfg(all(feature = "stats", feature = "std"))]
+                raw.add_compute_time(TypeId::of::<T>(), started.elapsed());
+
+                raw.insert(key, value)
+            }
+        };
+
+        self.take_last();
+        self.last.set(Some((key, ptr.cast())));
+        ptr
+    }
     /// Get or compute value using key
     #[inline]
     pub fn get<T: 'static + Send>(&self, key: impl FnOnce() -> T) -> &T {
         // SAFETY: pointer is valid and reference cannot outlive more than Self
         unsafe { self.get_ptr(key).as_ref() }
     }
-
     /// Get or compute value using key
+    ///
+    /// Map is borrowed exclusively before the closure runs,
+    /// so the closure cannot access the map and no value can be inserted during the borrow.
+    /// ```compile_fail
+    /// use fn_map::FnMap;
+    ///
+    /// let mut map = FnMap::new();
+    /// map.get_mut(|| map.get(|| 1) + 4);
+    /// ```
+    ///
+    /// Compute dependencies before borrowing exclusively instead.
+    /// ```
+    /// use fn_map::FnMap;
+    ///
+    /// let mut map = FnMap::new();
+    /// let one = *map.get(|| 1);
+    /// assert_eq!(*map.get_mut(|| one + 4), 5);
+    /// ```
     #[inline]
     pub fn get_mut<T: 'static + Send>(&mut self, key: impl FnOnce() -> T) -> &mut T {
+        // nothing is borrowed, so values can be dropped instead of retired
+        if self.auto_reset_due() {
+            self.reset();
+        }
+
         // SAFETY: pointer is valid and reference cannot outlive more than Self
         unsafe { self.get_ptr(key).as_mut() }
     }
-
-    /// Reset stored values
+    /// Get or compute value using key and return copy of it
+    ///
+    /// Map is not borrowed after return, unlike [`Self::get`].
     #[inline]
-    pub fn reset(&mut self) {
-        self.0.get_mut().reset();
+    pub fn get_copy<T: 'static + Send + Copy>(&self, key: impl FnOnce() -> T) -> T {
+        // SAFETY: pointer is valid and value is copied before returning
+        unsafe { *self.get_ptr(key).as_ref() }
     }
-}
+    /// Check both maps store values using same set of keys
+    #[inline]
+    pub fn keys_equal(&self, other: &Self) -> bool {
+        // SAFETY: safe to borrow shared because both maps are borrowed shared
+        unsafe { &*self.raw.get().cast_const() }
+            .keys_equal(unsafe { &*other.raw.get().cast_const() })
+    }
+    /// Check both maps store equal values using same set of keys
+    ///
+    /// Values of types registered in `registry` are compared, others are only compared by their keys.
+    pub fn values_equal(&self, other: &Self, registry: &EqRegistry) -> bool {
+        // SAFETY: safe to borrow shared because both maps are borrowed shared
+        let (raw, other) = unsafe {
+            (
+                &*self.raw.get().cast_const(),
+                &*other.raw.get().cast_const(),
+            )
+        };
 
-unsafe impl Send for FnMap {}
+        raw.keys_equal(other)
+            && raw.keys().all(|key| {
+                let (Some((a, Some(ty))), Some((b, other_ty))) =
+                    (raw.entry(&key), other.entry(&key))
+                else {
+                    return true;
+                };
 
-#[derive(Debug, Default)]
-/// Single thread only and non-Send FnMap implementation
-///
-/// This implementation is zero cost.
-pub struct LocalOnlyFnMap(UnsafeCell<RawFnMap>);
+                // key only determines type of the value within each map
+                if other_ty != Some(ty) {
+                    return false;
+                }
 
-impl LocalOnlyFnMap {
-    #[inline]
-    pub fn new() -> Self {
-        Self::default()
+                // SAFETY: both values are type of ty
+                unsafe { registry.eq(&ty, a, b) }.unwrap_or(true)
+            })
     }
-
-    #[inline]
-    pub fn get_ptr<T: 'static + Send>(&self, key_fn: impl FnOnce() -> T) -> NonNull<T> {
-        let key = TypeKey::of_val(&key_fn);
+    /// Get or compute value using key, consuming compute budget on miss
+    ///
+    /// Once the budget is exhausted, misses return value of `fallback` without storing it using the key.
+    /// Fallback values are kept alive until reset.
+    pub fn get_budgeted<T: 'static + Send>(
+        &self,
+        key_fn: impl FnOnce() -> T,
+        fallback: impl FnOnce() -> T,
+    ) -> &T {
+        let key = S::key(&key_fn);
 
         // SAFETY: safe to borrow shared because self is borrowed shared
-        if let Some(ptr) = unsafe { &*self.0.get().cast_const() }.get(&key) {
-            return ptr;
+        let stored = unsafe { &*self.raw.get().cast_const() }.get::<T>(&key);
+        if let Some(ptr) = stored {
+            // SAFETY: pointer is valid and reference cannot outlive more than Self
+            return unsafe { ptr.as_ref() };
         }
 
-        // accuire value first before borrowing exclusively
-        let value = key_fn();
+        if self.remaining_budget() == Some(0) {
+            let value = fallback();
 
-        // SAFETY: safe to borrow exclusively since no one can borrow more
-        unsafe { &mut *self.0.get() }.insert(key, value)
-    }
+            // SAFETY: safe to borrow exclusively since no one can borrow more
+            let ptr = unsafe { &mut *self.raw.get() }.insert_retired(value);
 
-    /// Get or compute value using key
+            // SAFETY: retired values are valid until reset
+            return unsafe { ptr.as_ref() };
+        }
+
+        self.misses.set(self.misses.get() + 1);
+
+        // SAFETY: key strategy guarantees key determines type of the value
+        unsafe { self.get_ptr_by_key(key, key_fn).as_ref() }
+    }
+    /// Borrow the map as a view which can only read stored values
     #[inline]
-    pub fn get<T: 'static + Send>(&self, key: impl FnOnce() -> T) -> &T {
-        // SAFETY: pointer is valid and reference cannot outlive more than Self
-        unsafe { self.get_ptr(key).as_ref() }
+    pub fn read_only(&self) -> ReadOnly<'_, S> {
+        ReadOnly::new(self)
     }
-
-    /// Get or compute value using key
+    /// Keys of stored values in ascending order
+    ///
+    /// Unlike iteration order of the map, the order is reproducible for same set of keys.
     #[inline]
-    pub fn get_mut<T: 'static + Send>(&mut self, key: impl FnOnce() -> T) -> &mut T {
-        // SAFETY: pointer is valid and reference cannot outlive more than Self
-        unsafe { self.get_ptr(key).as_mut() }
+    pub fn dump_sorted(&self) -> Vec<S::Key>
+    where
+        S::Key: Ord,
+    {
+        // SAFETY: safe to borrow shared because self is borrowed shared
+        unsafe { &*self.raw.get().cast_const() }.dump_sorted()
     }
+    /// Bytes of memory used by stored values since last reset
+    #[inline]
+    pub fn allocated_bytes(&self) -> usize {
+        // SAFETY: safe to borrow shared because self is borrowed shared
+        unsafe { &*self.raw.get().cast_const() }.allocated_bytes()
+    }
+    /// Write human readable summary of the map
+    ///
+    /// Summary includes number of values and memory usage,
+    /// hit and miss counts with `stats` feature and labeled values with `debug-keys` feature.
+    #[inline]
+    pub fn report(&self, w: &mut impl fmt::Write) -> fmt::Result {
+        #[cfg(feature = "stats")]
+        self.count_last_hits();
 
+        // SAFETY: safe to borrow shared because self is borrowed shared
+        unsafe { &*self.raw.get().cast_const() }.report(w)
+    }
+    /// Drop stored values but keep their memory allocated
+    ///
+    /// Without `slab` feature, memory of dropped values is not reused and accumulates until [`Self::reset`] is called.
+    #[inline]
+    pub fn clear(&mut self) {
+        self.raw_mut().clear();
+    }
     /// Reset stored values
     #[inline]
     pub fn reset(&mut self) {
-        self.0.get_mut().reset();
+        self.raw_mut().reset();
+        self.misses.set(0);
+        self.accesses.set(0);
+        self.auto_retired.set(false);
     }
-}
+    #[cfg(feature = "deps")]
+    fn id(&self) -> *const () {
+        (self as *const Self).cast()
+    }
+    /// Borrow raw map exclusively, invalidating last accessed value
+    #[inline]
+    fn raw_mut(&mut self) -> &mut RawFnMap<S::Hasher, S::Key> {
+        self.take_last();
+        self.raw.get_mut()
+    }
+    /// Invalidate last accessed value, counting its hits made without lookup
+    #[inline]
+    fn take_last(&self) {
+        #[cfg(feature = "stats")]
+        self.count_last_hits();
 
-#[derive(Debug, Default)]
-/// Thread safe FnMap implementation.
-///
-/// Uses parking_lot's [`RwLock`] to accuire mutable access to Map.
-pub struct ConcurrentFnMap(RwLock<RawFnMap>);
+        self.last.set(None);
+    }
+    /// Count hits of last accessed value made without lookup
+    #[cfg(feature = "stats")]
+    fn count_last_hits(&self) {
+        let (hits, generation) = self.last_hits.replace((0, 0));
 
-impl ConcurrentFnMap {
+        if let (Some((key, _)), 1..) = (self.last.get(), hits) {
+            // SAFETY: safe to borrow shared because self is borrowed shared
+            unsafe { &*self.raw.get().cast_const() }.add_hits(&key, hits, generation);
+        }
+    }
+}
+
+impl FnMap {
+    /// Get or build function using key and return it for calling
+    ///
+    /// Useful for caching function built from expensive setup.
     #[inline]
-    pub fn new() -> Self {
-        Self::default()
+    pub fn get_fn<Args: 'static, Ret: 'static>(
+        &self,
+        make: impl FnOnce() -> Box<dyn Fn(Args) -> Ret + Send>,
+    ) -> &dyn Fn(Args) -> Ret {
+        &**self.get(make)
     }
 
+    /// Get or load value using `loader`
+    ///
+    /// Value is keyed by type of the loader and value.
     #[inline]
-    pub fn get_ptr<T: 'static + Send + Sync>(&self, key_fn: impl FnOnce() -> T) -> NonNull<T> {
-        let key = TypeKey::of_val(&key_fn);
+    pub fn get_via<L: Loader<T>, T: 'static + Send>(&self, loader: &L) -> &T {
+        // SAFETY: key type contains type of the value
+        unsafe {
+            self.get_ptr_by_key(TypeKey::of::<(L, T)>(), || loader.load())
+                .as_ref()
+        }
+    }
 
-        if let Some(ptr) = self.0.read().get(&key) {
-            return ptr;
+    /// Get or compute value using key and discriminant `N`
+    ///
+    /// Each discriminant of same key has its own value.
+    #[inline]
+    pub fn get_variant<const N: usize, T: 'static + Send>(&self, key_fn: impl FnOnce() -> T) -> &T {
+        // SAFETY: closure type determines type of the value
+        unsafe {
+            self.get_ptr_by_key(variant_key::<N, _>(&key_fn), key_fn)
+                .as_ref()
         }
+    }
 
-        let value = key_fn();
+    /// Get or compute value using key in namespace `NS`
+    ///
+    /// Values of a namespace can be removed together using [`Self::reset_namespace`].
+    #[inline]
+    pub fn get_ns<const NS: u64, T: 'static + Send>(&self, key_fn: impl FnOnce() -> T) -> &T {
+        let key = ns_key::<NS, _>(&key_fn);
 
-        self.0.write().insert(key, value)
+        // SAFETY: closure type determines type of the value
+        let ptr = unsafe { self.get_ptr_by_key(key, key_fn) };
+
+        // SAFETY: safe to borrow exclusively since no one can borrow more
+        unsafe { &mut *self.raw.get() }.set_namespace(&key, NS);
+
+        // SAFETY: pointer is valid and reference cannot outlive more than Self
+        unsafe { ptr.as_ref() }
     }
 
-    /// Get or compute value using key
+    /// Get or compute value of runtime `key` using `key_fn`
+    ///
+    /// Each key closure has its own values for each runtime key.
+    /// Runtime keys are hashed with [`DefaultHashBuilder`], use [`Self::get_keyed_with_hasher`] to choose hasher.
     #[inline]
-    pub fn get<T: 'static + Send + Sync>(&self, key_fn: impl FnOnce() -> T) -> &T {
-        // SAFETY: pointer is valid and reference cannot outlive more than Self
-        unsafe { self.get_ptr(key_fn).as_ref() }
+    pub fn get_keyed<K: 'static + Eq + Hash + Send, T: 'static + Send>(
+        &self,
+        key: K,
+        key_fn: impl FnOnce(&K) -> T,
+    ) -> &T {
+        self.get_keyed_with_hasher::<DefaultHashBuilder, K, T>(key, key_fn)
     }
 
-    /// Get or compute value using key
+    /// Get or compute value of runtime `key` using `key_fn`, hashing runtime keys with `H`
+    pub fn get_keyed_with_hasher<H, K, T>(&self, key: K, key_fn: impl FnOnce(&K) -> T) -> &T
+    where
+        H: 'static + BuildHasher + Default + Send,
+        K: 'static + Eq + Hash + Send,
+        T: 'static + Send,
+    {
+        // SAFETY: key type contains closure, runtime key and hasher type
+        let values = unsafe {
+            self.get_ptr_by_key(keyed_key::<H, K, _>(&key_fn), || {
+                UnsafeCell::new(HashMap::<K, Box<T>, H>::default())
+            })
+            .as_ref()
+        };
+
+        // SAFETY: values are only borrowed here and not borrowed while computing
+        if let Some(value) = unsafe { &*values.get().cast_const() }.get(&key) {
+            // SAFETY: boxed values are never moved or dropped until the entry drops
+            return unsafe { &*(&**value as *const T) };
+        }
+
+        let value = key_fn(&key);
+
+        // SAFETY: safe to borrow exclusively since no one can borrow the table more
+        let value = unsafe { &mut *values.get() }
+            .entry(key)
+            .or_insert_with(|| Box::new(value));
+
+        // SAFETY: boxed values are never moved or dropped until the entry drops
+        unsafe { &*(&**value as *const T) }
+    }
+
+    /// Get value stored using explicit key, checking its type at runtime
+    ///
+    /// Returns `Ok(None)` if no value is stored using `key`,
+    /// or [`TypeMismatch`] if the stored value is not type of `T`.
+    pub fn get_checked<T: 'static>(&self, key: TypeKey) -> Result<Option<&T>, TypeMismatch> {
+        // SAFETY: safe to borrow shared because self is borrowed shared
+        let raw = unsafe { &*self.raw.get().cast_const() };
+
+        let Some((ptr, ty)) = raw.entry(&key) else {
+            return Ok(None);
+        };
+
+        if ty != Some(TypeKey::of::<T>()) {
+            return Err(TypeMismatch {
+                expected: any::type_name::<T>(),
+                found: raw.type_name(&key).unwrap_or_default(),
+            });
+        }
+
+        // SAFETY: stored value is type of T and reference cannot outlive more than Self
+        Ok(Some(unsafe { ptr.cast::<T>().as_ref() }))
+    }
+
+    /// Get or extract field of the value of `parent_key_fn` using `extract`
+    ///
+    /// Field is stored as its own entry keyed by both closures,
+    /// so it can be removed using [`Self::remove_field`] without recomputing the parent.
     #[inline]
-    pub fn get_mut<T: 'static + Send + Sync, F>(&mut self, key_fn: impl FnOnce() -> T) -> &mut T {
-        // SAFETY: pointer is valid and reference cannot outlive more than Self
-        unsafe { self.get_ptr(key_fn).as_mut() }
+    pub fn get_field<P: 'static + Send, U: 'static + Send>(
+        &self,
+        parent_key_fn: impl FnOnce() -> P,
+        extract: impl FnOnce(&P) -> U,
+    ) -> &U {
+        let key = field_key(&parent_key_fn, &extract);
+
+        // SAFETY: extract closure type determines type of the value
+        unsafe {
+            self.get_ptr_by_key(key, || extract(self.get(parent_key_fn)))
+                .as_ref()
+        }
     }
 
-    /// Reset stored values
+    /// Get or compute value using `f` receiving the map
+    ///
+    /// Value is keyed by type of `f`, so `f` can get other values from the map recursively.
+    ///
+    /// # Panics
+    /// Panics if computing the value requires the value itself
     #[inline]
-    pub fn reset(&mut self) {
-        self.0.get_mut().reset();
+    pub fn get_recursive<T: 'static + Send>(&self, f: impl FnOnce(&Self) -> T) -> &T {
+        let name = any::type_name_of_val(&f);
+
+        self.recursive_keyed((), |map, _| f(map), name)
     }
-}
 
-unsafe impl Send for ConcurrentFnMap {}
-unsafe impl Sync for ConcurrentFnMap {}
+    /// Get or compute value of runtime `key` using `f` receiving the map
+    ///
+    /// Like [`Self::get_keyed`], each runtime key has its own value,
+    /// so memoized recursive functions can get values of other keys from the map.
+    /// ```
+    /// use fn_map::FnMap;
+    ///
+    /// fn fib(map: &FnMap, n: u64) -> u64 {
+    ///     *map.get_recursive_keyed(n, |map, &n| match n {
+    ///         0 | 1 => n,
+    ///         _ => fib(map, n - 1) + fib(map, n - 2),
+    ///     })
+    /// }
+    ///
+    /// assert_eq!(fib(&FnMap::new(), 90), 2_880_067_194_370_816_120);
+    /// ```
+    ///
+    /// # Panics
+    /// Panics if computing the value requires the value of same key itself
+    pub fn get_recursive_keyed<K, T>(&self, key: K, f: impl FnOnce(&Self, &K) -> T) -> &T
+    where
+        K: 'static + Eq + Hash + Clone + Send,
+        T: 'static + Send,
+    {
+        let name = any::type_name_of_val(&f);
 
-#[cfg(test)]
-mod tests {
-    use crate::LocalOnlyFnMap;
+        self.recursive_keyed(key, f, name)
+    }
 
-    use super::{ConcurrentFnMap, FnMap};
+    fn recursive_keyed<K, T>(
+        &self,
+        key: K,
+        f: impl FnOnce(&Self, &K) -> T,
+        name: &'static str,
+    ) -> &T
+    where
+        K: 'static + Eq + Hash + Clone + Send,
+        T: 'static + Send,
+    {
+        let id = TypeKey::of_val(&f);
 
-    #[test]
-    fn test_trait() {
-        const fn is_send<T: Send>() {}
-        const fn is_sync<T: Sync>() {}
+        self.get_keyed(key, |key| {
+            let _recursing = Recursing::enter(&self.recursing, id, key.clone(), name);
 
-        is_send::<FnMap>();
+            f(self, key)
+        })
+    }
 
-        is_send::<ConcurrentFnMap>();
-        is_sync::<ConcurrentFnMap>();
+    /// Get or compute value using key, recomputing it if stored value is older than `version`
+    ///
+    /// Values stored without version have version 0.
+    pub fn get_versioned<T: 'static + Send>(
+        &mut self,
+        key_fn: impl FnOnce() -> T,
+        version: u64,
+    ) -> &T {
+        let key = TypeKey::of_val(&key_fn);
+        let raw = self.raw_mut();
+
+        let ptr = match raw.version(&key) {
+            Some(stored) if stored >= version => raw.get::<T>(&key).unwrap(),
+
+            _ => {
+                raw.remove(&key);
+                let ptr = raw.insert(key, key_fn());
+                raw.set_version(&key, version);
+
+                ptr
+            }
+        };
+
+        // SAFETY: pointer is valid and reference cannot outlive more than Self
+        unsafe { ptr.as_ref() }
     }
 
-    #[test]
-    fn test_local() {
-        let map = FnMap::new();
+    /// Get or compute result using key, retrying cached error after `retry_after` ticks of `clock` elapsed
+    ///
+    /// Error is returned from cache until `retry_after` elapses from its computation.
+    /// Replaced error is kept alive until reset since it can be still borrowed.
+    pub fn get_with_retry<T: 'static + Send, E: 'static + Send>(
+        &self,
+        key_fn: impl FnOnce() -> Result<T, E>,
+        retry_after: u64,
+        clock: &impl Clock,
+    ) -> Result<&T, &E> {
+        let key = retry_key(&key_fn);
 
-        fn one() -> i32 {
-            1
+        // SAFETY: safe to borrow shared because self is borrowed shared
+        let raw = unsafe { &*self.raw.get().cast_const() };
+        if let Some(ptr) = raw.get::<(Result<T, E>, u64)>(&key) {
+            // SAFETY: pointer is valid and reference cannot outlive more than Self
+            let (res, computed_at) = unsafe { ptr.as_ref() };
+
+            if res.is_ok() || clock.now().saturating_sub(*computed_at) < retry_after {
+                return res.as_ref();
+            }
         }
 
-        let b = map.get(|| map.get(one) + 1);
-        let a = map.get(one);
+        let value = (key_fn(), clock.now());
 
-        assert_eq!(*b, 2);
-        assert_eq!(*a, 1);
+        // SAFETY: safe to borrow exclusively since no one can borrow more
+        let raw = unsafe { &mut *self.raw.get() };
+        raw.retire(&key);
+        let ptr = raw.insert(key, value);
+
+        // SAFETY: pointer is valid and reference cannot outlive more than Self
+        unsafe { ptr.as_ref() }.0.as_ref()
     }
 
-    #[test]
-    fn test_local_only() {
-        let map = LocalOnlyFnMap::new();
+    /// Get or compute value using key, recomputing it once `ttl` ticks of `clock` elapsed from its computation
+    ///
+    /// Expired value is kept alive until reset since it can be still borrowed.
+    pub fn get_with_ttl<T: 'static + Send>(
+        &self,
+        key_fn: impl FnOnce() -> T,
+        ttl: u64,
+        clock: &impl Clock,
+    ) -> &T {
+        let key = ttl_key(&key_fn);
 
-        fn one() -> i32 {
-            1
+        // SAFETY: safe to borrow shared because self is borrowed shared
+        let raw = unsafe { &*self.raw.get().cast_const() };
+        if let Some(ptr) = raw.get::<(T, u64)>(&key) {
+            // SAFETY: pointer is valid and reference cannot outlive more than Self
+            let (value, computed_at) = unsafe { ptr.as_ref() };
+
+            if clock.now().saturating_sub(*computed_at) < ttl {
+                return value;
+            }
         }
 
-        let b = map.get(|| map.get(one) + 1);
-        let a = map.get(one);
+        let value = (key_fn(), clock.now());
 
-        assert_eq!(*b, 2);
-        assert_eq!(*a, 1);
+        // SAFETY: safe to borrow exclusively since no one can borrow more
+        let raw = unsafe { &mut *self.raw.get() };
+        raw.retire(&key);
+        let ptr = raw.insert(key, value);
+
+        // SAFETY: pointer is valid and reference cannot outlive more than Self
+        &unsafe { ptr.as_ref() }.0
     }
 
-    #[test]
-    fn test_atomic() {
-        let map = ConcurrentFnMap::new();
+    /// Compute value using previously stored value and store it
+    ///
+    /// Stored value is moved out to `f`, or `None` is passed if it is missing.
+    /// `key_fn` is only used as key, so the value is shared with [`Self::get`].
+    pub fn recompute<T: 'static + Send + Unpin>(
+        &mut self,
+        key_fn: impl FnOnce() -> T,
+        f: impl FnOnce(Option<T>) -> T,
+    ) -> &T {
+        let key = TypeKey::of_val(&key_fn);
+        let raw = self.raw_mut();
 
-        fn one() -> i32 {
-            1
-        }
+        // SAFETY: closure type determines type of the value and Unpin values can be moved
+        let old = unsafe { raw.take::<T>(&key) };
+        let ptr = raw.insert(key, f(old));
 
-        let b = map.get(|| map.get(one) + 1);
-        let a = map.get(one);
+        // SAFETY: pointer is valid and reference cannot outlive more than Self
+        unsafe { ptr.as_ref() }
+    }
+
+    /// Move value stored using key into [`Arc`] and return it
+    ///
+    /// Value is computed first if it is missing.
+    /// Later calls return clones of the same [`Arc`], while [`Self::get`] computes the value again.
+    pub fn share<T: 'static + Send + Sync + Unpin>(
+        &mut self,
+        key_fn: impl FnOnce() -> T,
+    ) -> Arc<T> {
+        let shared_key = arc_key(&key_fn);
+        let raw = self.raw_mut();
+
+        if let Some(ptr) = raw.get::<Arc<T>>(&shared_key) {
+            // SAFETY: key type determines type of the value
+            return unsafe { ptr.as_ref() }.clone();
+        }
+
+        // SAFETY: closure type determines type of the value and Unpin values can be moved
+        let value = match unsafe { raw.take::<T>(&TypeKey::of_val(&key_fn)) } {
+            Some(value) => value,
+            None => key_fn(),
+        };
+
+        let shared = Arc::new(value);
+        raw.insert(shared_key, shared.clone());
+        shared
+    }
+
+    /// Get or compute value using key and remove it when returned guard drops
+    ///
+    /// Useful for values only needed during an operation.
+    #[inline]
+    pub fn get_scoped<T: 'static + Send>(
+        &mut self,
+        key_fn: impl FnOnce() -> T,
+    ) -> ScopedValue<'_, T> {
+        let key = TypeKey::of_val(&key_fn);
+        let ptr = self.get_ptr(key_fn);
+
+        ScopedValue {
+            map: self,
+            key,
+            ptr,
+        }
+    }
+
+    /// Get or compute value using key and pin it
+    ///
+    /// Stored values are never moved until they are dropped, so they can be pinned.
+    #[inline]
+    pub fn get_pin_mut<T: 'static + Send>(&mut self, key_fn: impl FnOnce() -> T) -> Pin<&mut T> {
+        // SAFETY: value stays in same place until it is dropped
+        unsafe { Pin::new_unchecked(self.get_mut(key_fn)) }
+    }
+
+    /// Get or compute value using key and return weak reference to it
+    #[inline]
+    pub fn get_weak<T: 'static + Send>(&self, key_fn: impl FnOnce() -> T) -> WeakRef<T> {
+        let ptr = self.get_ptr(key_fn);
+
+        WeakRef {
+            ptr,
+            // SAFETY: safe to borrow shared because self is borrowed shared
+            generation: unsafe { &*self.raw.get().cast_const() }.generation(),
+        }
+    }
+
+    /// Get or compute value using key and return handle resolving it later
+    ///
+    /// Unlike [`WeakRef`], handle also checks the key is still stored in the map.
+    #[inline]
+    pub fn handle<T: 'static + Send>(&self, key_fn: impl FnOnce() -> T) -> Handle<T> {
+        let key = TypeKey::of_val(&key_fn);
+        let ptr = self.get_ptr(key_fn);
+
+        Handle {
+            key,
+            // SAFETY: safe to borrow shared because self is borrowed shared
+            generation: unsafe { &*self.raw.get().cast_const() }.generation(),
+            ptr,
+        }
+    }
+
+    /// Get or compute value using key and return pointer to it surviving moves of the map
+    #[inline]
+    pub fn stable_ptr<T: 'static + Send>(&self, key_fn: impl FnOnce() -> T) -> StablePtr<T> {
+        StablePtr {
+            ptr: self.get_ptr(key_fn),
+        }
+    }
+
+    /// Get or compute value using key, storing it at address aligned to `align`
+    ///
+    /// # Panics
+    /// Panics if `align` is not power of two or is smaller than alignment of `T`,
+    /// or if the value is already stored with smaller alignment.
+    pub fn get_aligned<T: 'static + Send>(&self, key_fn: impl FnOnce() -> T, align: usize) -> &T {
+        assert!(
+            align.is_power_of_two() && align >= align_of::<T>(),
+            "invalid alignment"
+        );
+        let key = aligned_key(&key_fn);
+
+        // SAFETY: safe to borrow shared because self is borrowed shared
+        let ptr = match unsafe { &*self.raw.get().cast_const() }.get::<T>(&key) {
+            Some(ptr) => {
+                assert!(
+                    ptr.as_ptr().align_offset(align) == 0,
+                    "value is stored with smaller alignment"
+                );
+                ptr
+            }
+
+            None => {
+                let value = key_fn();
+
+                // SAFETY: safe to borrow exclusively since no one can borrow more
+                unsafe { &mut *self.raw.get() }.insert_aligned(key, value, align)
+            }
+        };
+
+        // SAFETY: pointer is valid and reference cannot outlive more than Self
+        unsafe { ptr.as_ref() }
+    }
+
+    /// Look up value using key without computing it
+    ///
+    /// On miss, returned [`MissToken`] stores a value provided later.
+    #[inline]
+    pub fn probe<T: 'static + Send>(&self, key_fn: impl FnOnce() -> T) -> Probe<'_, T> {
+        let key = TypeKey::of_val(&key_fn);
+
+        // SAFETY: safe to borrow shared because self is borrowed shared
+        match unsafe { &*self.raw.get().cast_const() }.get::<T>(&key) {
+            // SAFETY: closure type determines type of the value
+            Some(ptr) => Probe::Hit(unsafe { ptr.as_ref() }),
+
+            None => Probe::Miss(MissToken {
+                map: self,
+                key,
+                _value: PhantomData,
+            }),
+        }
+    }
+
+    /// Get or compute byte buffer using key and return view of its contents
+    #[inline]
+    pub fn get_bytes_view(&self, key_fn: impl FnOnce() -> Vec<u8>) -> &[u8] {
+        self.get(key_fn)
+    }
+
+    /// Get or compute byte buffer using key, storing it compressed
+    ///
+    /// Only compressed bytes are stored in the map, and they are decompressed into a new buffer on every access.
+    /// It trades CPU time of each access for memory, so it suits large blobs accessed rarely.
+    #[cfg(feature = "compress")]
+    pub fn get_compressed(&self, key_fn: impl FnOnce() -> Vec<u8>) -> Vec<u8> {
+        // lengths of compressed and decompressed bytes, followed by compressed bytes
+        type Header = [usize; 2];
+
+        let key = compressed_key(&key_fn);
+
+        // SAFETY: safe to borrow shared because self is borrowed shared
+        let raw = unsafe { &*self.raw.get().cast_const() };
+        if let Some(ptr) = raw.get::<()>(&key) {
+            let ptr = ptr.as_ptr().cast::<u8>();
+
+            // SAFETY: every values stored using key are header followed by compressed bytes
+            let (compressed, len) = unsafe {
+                let [compressed_len, len] = ptr.cast::<Header>().read();
+                let bytes = ptr.add(core::mem::size_of::<Header>());
+
+                (slice::from_raw_parts(bytes, compressed_len), len)
+            };
+
+            return compress::decompress(compressed, len);
+        }
+
+        let bytes = key_fn();
+        let compressed = compress::compress(&bytes);
+        let (layout, offset) = Layout::new::<Header>()
+            .extend(Layout::array::<u8>(compressed.len()).unwrap())
+            .unwrap();
+
+        // SAFETY: bytes need no drop
+        unsafe fn drop_nothing(_: *mut u8) {}
+
+        // SAFETY: safe to borrow exclusively since no one can borrow more
+        let raw = unsafe { &mut *self.raw.get() };
+        // SAFETY: header and compressed bytes are initialized
+        unsafe {
+            raw.insert_dst(
+                key,
+                layout,
+                |ptr| {
+                    ptr.cast::<Header>().write([compressed.len(), bytes.len()]);
+                    ptr.add(offset)
+                        .copy_from_nonoverlapping(compressed.as_ptr(), compressed.len());
+                },
+                drop_nothing,
+            )
+        };
+
+        bytes
+    }
+
+    /// Get or compute collection using key and iterate its elements
+    #[inline]
+    pub fn get_iter<U: 'static + Send>(
+        &self,
+        key_fn: impl FnOnce() -> Vec<U>,
+    ) -> slice::Iter<'_, U> {
+        self.get(key_fn).iter()
+    }
+
+    /// Get or compute value using key, labeling the entry with `label`
+    ///
+    /// Label is only recorded with `debug-keys` feature.
+    #[inline]
+    pub fn get_labeled<T: 'static + Send>(
+        &self,
+        label: &'static str,
+        key_fn: impl FnOnce() -> T,
+    ) -> &T {
+        let key = TypeKey::of_val(&key_fn);
+
+        // SAFETY: closure type determines type of the value
+        let ptr = unsafe { self.get_ptr_by_key(key, key_fn) };
+
+        // SAFETY: safe to borrow exclusively since no one can borrow more
+        #[cfg(feature = "debug-keys")]
+        unsafe { &mut *self.raw.get() }.set_label(&key, label);
+        #[cfg(not(feature = "debug-keys"))]
+        let _ = label;
+
+        // SAFETY: pointer is valid and reference cannot outlive more than Self
+        unsafe { ptr.as_ref() }
+    }
+
+    /// Get or compute value using key, caching it only if `should_cache` returns true
+    ///
+    /// Value not cached is returned as owned.
+    pub fn get_cow<T: 'static + Send + Clone>(
+        &self,
+        key_fn: impl FnOnce() -> T,
+        should_cache: impl FnOnce(&T) -> bool,
+    ) -> Cow<'_, T> {
+        let key = TypeKey::of_val(&key_fn);
+
+        // SAFETY: safe to borrow shared because self is borrowed shared
+        if let Some(ptr) = unsafe { &*self.raw.get().cast_const() }.get::<T>(&key) {
+            // SAFETY: pointer is valid and reference cannot outlive more than Self
+            return Cow::Borrowed(unsafe { ptr.as_ref() });
+        }
+
+        let value = key_fn();
+        if !should_cache(&value) {
+            return Cow::Owned(value);
+        }
+
+        // SAFETY: safe to borrow exclusively since no one can borrow more
+        let ptr = unsafe { &mut *self.raw.get() }.insert(key, value);
+
+        // SAFETY: pointer is valid and reference cannot outlive more than Self
+        Cow::Borrowed(unsafe { ptr.as_ref() })
+    }
+
+    /// Get or compute value using explicit key and erased initializer
+    ///
+    /// # Safety
+    /// Every value stored using `key` must be type of `T`
+    #[inline]
+    pub unsafe fn get_erased<T: 'static + Send>(
+        &self,
+        key: TypeKey,
+        init: Box<dyn FnOnce() -> T + '_>,
+    ) -> &T {
+        // SAFETY: pointer is valid and reference cannot outlive more than Self
+        unsafe { self.get_ptr_by_key(key, init).as_ref() }
+    }
+
+    /// Get or initialize value in place using key
+    ///
+    /// Value is initialized directly in the map's storage so large values are never moved.
+    /// `key_fn` is only used as key.
+    ///
+    /// # Safety
+    /// `init` must fully initialize the value
+    pub unsafe fn get_emplace<T: 'static + Send>(
+        &self,
+        key_fn: impl FnOnce() -> T,
+        init: impl FnOnce(&mut MaybeUninit<T>),
+    ) -> &T {
+        // SAFETY: guaranteed by caller
+        let res = unsafe {
+            self.try_get_emplace::<T, Infallible>(key_fn, |value| {
+                init(value);
+                Ok(())
+            })
+        };
+
+        match res {
+            Ok(value) => value,
+            Err(never) => match never {},
+        }
+    }
+
+    /// Get or initialize value in place using key, failing if `init` returns error
+    ///
+    /// Space reserved for the value is discarded without storing the value if `init` fails.
+    /// `key_fn` is only used as key.
+    ///
+    /// # Safety
+    /// `init` must fully initialize the value if it returns `Ok`
+    pub unsafe fn try_get_emplace<T: 'static + Send, E>(
+        &self,
+        key_fn: impl FnOnce() -> T,
+        init: impl FnOnce(&mut MaybeUninit<T>) -> Result<(), E>,
+    ) -> Result<&T, E> {
+        let key = TypeKey::of_val(&key_fn);
+
+        // SAFETY: safe to borrow shared because self is borrowed shared
+        let raw = unsafe { &*self.raw.get().cast_const() };
+        if let Some(ptr) = raw.get::<T>(&key) {
+            // SAFETY: pointer is valid and reference cannot outlive more than Self
+            return Ok(unsafe { ptr.as_ref() });
+        }
+
+        // SAFETY: safe to borrow exclusively since no one can borrow more
+        let mut ptr = unsafe { (*self.raw.get()).alloc_uninit::<T>() };
+        // SAFETY: allocated space is not shared with anyone
+        if let Err(err) = init(unsafe { ptr.as_mut() }) {
+            // SAFETY: space is allocated from the map and not initialized
+            unsafe { (*self.raw.get()).dealloc_uninit(ptr) };
+            return Err(err);
+        }
+
+        // SAFETY: value is initialized by caller and no one can borrow more
+        Ok(unsafe { (*self.raw.get()).insert_ptr(key, ptr.cast::<T>()).as_ref() })
+    }
+
+    /// Get or compute values using explicit keys and erased initializers
+    ///
+    /// Repeated keys in one batch are computed once and resolved to same reference.
+    ///
+    /// # Safety
+    /// Every value stored using each key must be type of `T`
+    pub unsafe fn bulk_get<'a, T: 'static + Send>(
+        &self,
+        entries: impl IntoIterator<Item = (TypeKey, Box<dyn FnOnce() -> T + 'a>)>,
+    ) -> Vec<&T> {
+        let mut resolved = HashMap::<TypeKey, NonNull<T>, BuildNoHashHasher<u64>>::default();
+
+        entries
+            .into_iter()
+            .map(|(key, init)| {
+                let ptr = *resolved
+                    .entry(key)
+                    // SAFETY: guaranteed by caller
+                    .or_insert_with(|| unsafe { self.get_ptr_by_key(key, init) });
+
+                // SAFETY: pointer is valid and reference cannot outlive more than Self
+                unsafe { ptr.as_ref() }
+            })
+            .collect()
+    }
+
+    /// Call `f` with label and key of every stored values
+    #[cfg(feature = "debug-keys")]
+    #[inline]
+    pub fn dump(&self, f: impl FnMut(&'static str, TypeKey)) {
+        // SAFETY: safe to borrow shared because self is borrowed shared
+        unsafe { &*self.raw.get().cast_const() }.dump(f);
+    }
+
+    /// Bytes which can be allocated before a new chunk is allocated
+    #[inline]
+    pub fn remaining_chunk_capacity(&self) -> usize {
+        // SAFETY: safe to borrow shared because self is borrowed shared
+        unsafe { &*self.raw.get().cast_const() }.remaining_chunk_capacity()
+    }
+
+    /// Remove and drop value stored using key
+    ///
+    /// Returns `true` if the value was stored.
+    #[inline]
+    pub fn remove<T: 'static + Send>(&mut self, key_fn: impl FnOnce() -> T) -> bool {
+        self.raw_mut().remove(&TypeKey::of_val(&key_fn))
+    }
+
+    /// Remove and drop field stored by [`Self::get_field`], keeping its parent
+    #[inline]
+    pub fn remove_field<P: 'static, U: 'static>(
+        &mut self,
+        parent_key_fn: impl FnOnce() -> P,
+        extract: impl FnOnce(&P) -> U,
+    ) -> bool {
+        self.raw_mut().remove(&field_key(&parent_key_fn, &extract))
+    }
+
+    /// Remove and drop every values of type `T`
+    #[inline]
+    pub fn invalidate_type<T: 'static>(&mut self) {
+        self.raw_mut().remove_type(&TypeKey::of::<T>());
+    }
+
+    /// Remove and drop every values stored in namespace `NS`
+    #[inline]
+    pub fn reset_namespace<const NS: u64>(&mut self) {
+        self.raw_mut().remove_namespace(NS);
+    }
+
+    /// Remove value stored using key and every values depending on it
+    ///
+    /// Dependents are found transitively from [`Self::dependencies`].
+    #[cfg(feature = "deps")]
+    #[inline]
+    pub fn invalidate_cascade<T: 'static + Send>(&mut self, key_fn: impl FnOnce() -> T) {
+        self.raw_mut().remove_cascade(&TypeKey::of_val(&key_fn));
+    }
+
+    /// Hit and miss counts of the map
+    ///
+    /// Counts are kept across [`Self::reset`].
+    #[cfg(feature = "stats")]
+    #[inline]
+    pub fn stats(&self) -> Stats {
+        self.count_last_hits();
+
+        // SAFETY: safe to borrow shared because self is borrowed shared
+        unsafe { &*self.raw.get().cast_const() }.stats()
+    }
+
+    /// Statistics, number of values and allocated bytes of the map as OpenTelemetry shaped metrics labeled with its name
+    #[cfg(feature = "otel")]
+    #[inline]
+    pub fn export_metrics(&self) -> Vec<Metric> {
+        #[cfg(feature = "stats")]
+        self.count_last_hits();
+
+        // SAFETY: safe to borrow shared because self is borrowed shared
+        unsafe { &*self.raw.get().cast_const() }.export_metrics()
+    }
+
+    /// Pass every metric of [`Self::export_metrics`] to `f`
+    ///
+    /// Meant to be called from callback of observable instruments registered with a meter.
+    #[cfg(feature = "otel")]
+    #[inline]
+    pub fn register_metrics(&self, f: impl FnMut(&Metric)) {
+        self.export_metrics().iter().for_each(f);
+    }
+
+    /// Read statistics of value stored using key
+    #[cfg(feature = "stats")]
+    #[inline]
+    pub fn entry_stats<T: 'static>(&self, key_fn: impl FnOnce() -> T) -> Option<EntryStats> {
+        #[cfg(feature = "stats")]
+        self.count_last_hits();
+
+        // SAFETY: safe to borrow shared because self is borrowed shared
+        unsafe { &*self.raw.get().cast_const() }.entry_stats(&TypeKey::of_val(&key_fn))
+    }
+
+    /// Cumulative time spent computing values of each type
+    ///
+    /// Only computations of [`Self::get`] and its variants using same path are timed.
+    #[cfg(all(feature = "stats", feature = "std"))]
+    #[inline]
+    pub fn compute_time_by_type(&self) -> Vec<(TypeId, Duration)> {
+        // SAFETY: safe to borrow shared because self is borrowed shared
+        unsafe { &*self.raw.get().cast_const() }
+            .compute_time()
+            .to_vec()
+    }
+
+    /// Reset stored values and hit and miss counts
+    #[cfg(feature = "stats")]
+    #[inline]
+    pub fn reset_stats_too(&mut self) {
+        self.reset();
+        self.raw_mut().reset_stats();
+    }
+
+    /// Convert into read only map
+    #[inline]
+    pub fn freeze(self) -> FrozenFnMap {
+        self.take_last();
+        FrozenFnMap(self.raw.into_inner())
+    }
+
+    /// Recorded (dependent, dependency) pairs of keys
+    ///
+    /// A value depends on every values accessed from the map while it is computed.
+    #[cfg(feature = "deps")]
+    #[inline]
+    pub fn dependencies(&self) -> Vec<(TypeKey, TypeKey)> {
+        // SAFETY: safe to borrow shared because self is borrowed shared
+        unsafe { &*self.raw.get().cast_const() }
+            .dependencies()
+            .to_vec()
+    }
+
+    /// Convert into read only map shareable between threads
+    #[cfg_attr(feature = "no-unsafe-marker", allow(clippy::arc_with_non_send_sync))]
+    #[inline]
+    pub fn into_frozen_arc(self) -> Arc<FrozenFnMap> {
+        Arc::new(self.freeze())
+    }
+}
+
+/// Marks closure and its argument as being computed by [`FnMap::get_recursive_keyed`] until dropped
+struct Recursing<'a>(&'a RefCell<Vec<(TypeKey, Box<dyn Any + Send>)>>);
+
+impl<'a> Recursing<'a> {
+    fn enter<K: 'static + PartialEq + Send>(
+        recursing: &'a RefCell<Vec<(TypeKey, Box<dyn Any + Send>)>>,
+        id: TypeKey,
+        key: K,
+        name: &'static str,
+    ) -> Self {
+        let mut stack = recursing.borrow_mut();
+
+        let cyclic = stack
+            .iter()
+            .any(|(computing, arg)| *computing == id && arg.downcast_ref::<K>() == Some(&key));
+        if cyclic {
+            drop(stack);
+            panic!("cycle detected while computing `{name}`");
+        }
+
+        stack.push((id, Box::new(key)));
+        Self(recursing)
+    }
+}
+
+impl Drop for Recursing<'_> {
+    fn drop(&mut self) {
+        self.0.borrow_mut().pop();
+    }
+}
+
+/// Hook of [`FnMap::set_on_miss`]
+#[cfg(feature = "debug-keys")]
+#[derive(Default)]
+struct MissHook(Cell<Option<Box<MissFn>>>);
+
+#[cfg(feature = "debug-keys")]
+type MissFn = dyn FnMut(&'static str, TypeKey) + Send;
+
+#[cfg(feature = "debug-keys")]
+impl MissHook {
+    fn call(&self, name: &'static str, key: TypeKey) {
+        // taken out while calling, so it cannot be borrowed twice
+        if let Some(mut f) = self.0.take() {
+            f(name, key);
+            self.0.set(Some(f));
+        }
+    }
+}
+
+#[cfg(feature = "debug-keys")]
+impl fmt::Debug for MissHook {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("MissHook")
+    }
+}
+
+#[cfg(not(feature = "no-unsafe-marker"))]
+unsafe impl<S: KeyStrategy> Send for FnMap<S> {}
+
+#[derive(Debug)]
+/// Weak reference to a value stored in [`FnMap`]
+///
+/// It cannot be upgraded after any value of the map is dropped, including by reset.
+pub struct WeakRef<T> {
+    ptr: NonNull<T>,
+    generation: u64,
+}
+
+impl<T> WeakRef<T> {
+    /// Get referenced value if it is still valid in `map`
+    #[inline]
+    pub fn upgrade<'a>(&self, map: &'a FnMap) -> Option<&'a T> {
+        // SAFETY: safe to borrow shared because map is borrowed shared
+        if unsafe { &*map.raw.get().cast_const() }.generation() != self.generation {
+            return None;
+        }
+
+        // SAFETY: no values of the map are dropped since it is created
+        Some(unsafe { self.ptr.as_ref() })
+    }
+}
+
+impl<T> Clone for WeakRef<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T> Copy for WeakRef<T> {}
+
+#[derive(Debug)]
+/// Handle to a value stored in [`FnMap`]
+///
+/// It resolves to the value until the value is removed or any value of the map is dropped, including by reset.
+pub struct Handle<T> {
+    key: TypeKey,
+    generation: u64,
+    ptr: NonNull<T>,
+}
+
+impl<T> Handle<T> {
+    /// Key of the value
+    #[inline]
+    pub const fn key(&self) -> TypeKey {
+        self.key
+    }
+
+    /// Get value if the handle is still valid in `map`
+    #[inline]
+    pub fn get<'a>(&self, map: &'a FnMap) -> Option<&'a T> {
+        // SAFETY: safe to borrow shared because map is borrowed shared
+        let raw = unsafe { &*map.raw.get().cast_const() };
+
+        if raw.generation() != self.generation {
+            return None;
+        }
+
+        let (ptr, _) = raw.entry(&self.key)?;
+        if ptr != self.ptr.cast() {
+            return None;
+        }
+
+        // SAFETY: value is stored in the map and no values of the map are dropped since the handle is created
+        Some(unsafe { self.ptr.as_ref() })
+    }
+}
+
+impl<T> Clone for Handle<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T> Copy for Handle<T> {}
+
+#[derive(Debug)]
+/// Pointer to a value stored in [`FnMap`] which stays valid when the map is moved
+///
+/// Values are stored in heap chunks of the bump, which are not moved with the map.
+/// Pointer is invalidated when the value is dropped, including by reset or dropping the map.
+pub struct StablePtr<T> {
+    ptr: NonNull<T>,
+}
+
+impl<T> StablePtr<T> {
+    #[inline]
+    pub const fn as_ptr(&self) -> NonNull<T> {
+        self.ptr
+    }
+
+    /// # Safety
+    /// The value must not be dropped while returned reference is alive,
+    /// and it must not be borrowed exclusively from the map.
+    #[inline]
+    pub unsafe fn as_ref<'a>(&self) -> &'a T {
+        // SAFETY: guaranteed by caller
+        unsafe { self.ptr.as_ref() }
+    }
+}
+
+impl<T> Clone for StablePtr<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T> Copy for StablePtr<T> {}
+
+#[derive(Debug)]
+/// Result of [`FnMap::probe`]
+pub enum Probe<'a, T> {
+    Hit(&'a T),
+    Miss(MissToken<'a, T>),
+}
+
+#[derive(Debug)]
+/// Missed key of [`FnMap::probe`], storing a value provided later
+pub struct MissToken<'a, T> {
+    map: &'a FnMap,
+    key: TypeKey,
+    _value: PhantomData<fn() -> T>,
+}
+
+impl<'a, T: 'static + Send> MissToken<'a, T> {
+    /// Key of the missed value
+    #[inline]
+    pub const fn key(&self) -> TypeKey {
+        self.key
+    }
+
+    /// Store `value` and return reference to it
+    ///
+    /// If a value is stored after probing, stored value is returned and `value` is dropped.
+    #[inline]
+    pub fn compute(self, value: T) -> &'a T {
+        // SAFETY: key is derived from closure returning T
+        unsafe { self.map.get_ptr_by_key(self.key, || value).as_ref() }
+    }
+}
+
+#[derive(Debug)]
+/// Value of [`FnMap::get_scoped`] removed from the map on drop
+pub struct ScopedValue<'a, T> {
+    map: &'a mut FnMap,
+    key: TypeKey,
+    ptr: NonNull<T>,
+}
+
+impl<T> Deref for ScopedValue<'_, T> {
+    type Target = T;
+
+    #[inline]
+    fn deref(&self) -> &T {
+        // SAFETY: value is removed only when the guard drops
+        unsafe { self.ptr.as_ref() }
+    }
+}
+
+impl<T> Drop for ScopedValue<'_, T> {
+    fn drop(&mut self) {
+        self.map.raw_mut().remove(&self.key);
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// Error of [`FnMap::get_checked`] requesting value as a different type
+pub struct TypeMismatch {
+    /// Name of requested type
+    pub expected: &'static str,
+    /// Name of type of the stored value
+    pub found: &'static str,
+}
+
+impl fmt::Display for TypeMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "stored value is type of `{}`, not `{}`",
+            self.found, self.expected
+        )
+    }
+}
+
+impl core::error::Error for TypeMismatch {}
+
+#[derive(Debug)]
+/// Read only FnMap created from [`FnMap::freeze`].
+///
+/// Only [`Sync`] values can be accessed so it can be shared between threads without locking.
+pub struct FrozenFnMap(RawFnMap);
+
+impl FrozenFnMap {
+    /// Get value stored using key
+    #[inline]
+    pub fn get<T: 'static + Send + Sync>(&self, key_fn: impl FnOnce() -> T) -> Option<&T> {
+        let ptr = self.0.get::<T>(&TypeKey::of_val(&key_fn))?;
+
+        // SAFETY: pointer is valid and reference cannot outlive more than Self
+        Some(unsafe { ptr.as_ref() })
+    }
+
+    /// Keys of stored values in ascending order
+    ///
+    /// Unlike iteration order of the map, the order is reproducible for same set of keys.
+    #[inline]
+    pub fn dump_sorted(&self) -> Vec<TypeKey> {
+        self.0.dump_sorted()
+    }
+}
+
+// SAFETY: values are Send and only Sync values can be accessed
+#[cfg(not(feature = "no-unsafe-marker"))]
+unsafe impl Send for FrozenFnMap {}
+#[cfg(not(feature = "no-unsafe-marker"))]
+unsafe impl Sync for FrozenFnMap {}
+
+#[derive(Debug, Default)]
+/// Single thread only and non-Send FnMap implementation
+///
+/// This implementation is zero cost.
+pub struct LocalOnlyFnMap(UnsafeCell<RawFnMap>);
+
+impl LocalOnlyFnMap {
+    #[inline]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Label map with `name` to distinguish it from other maps
+    #[inline]
+    pub fn with_name(mut self, name: &'static str) -> Self {
+        self.0.get_mut().set_name(name);
+        self
+    }
+
+    /// Label of the map
+    #[inline]
+    pub fn name(&self) -> Option<&'static str> {
+        // SAFETY: name is only changed with exclusive borrow
+        unsafe { &*self.0.get().cast_const() }.name()
+    }
+
+    #[inline]
+    pub fn get_ptr<T: 'static + Send>(&self, key_fn: impl FnOnce() -> T) -> NonNull<T> {
+        // SAFETY: closure type determines type of the value
+        unsafe { self.get_ptr_by_key(TypeKey::of_val(&key_fn), key_fn) }
+    }
+
+    /// Get or compute value using explicit key
+    ///
+    /// # Safety
+    /// Every value stored using `key` must be type of `T`
+    #[inline]
+    pub unsafe fn get_ptr_by_key<T: 'static + Send>(
+        &self,
+        key: TypeKey,
+        init: impl FnOnce() -> T,
+    ) -> NonNull<T> {
+        // SAFETY: safe to borrow shared because self is borrowed shared
+        if let Some(ptr) = unsafe { &*self.0.get().cast_const() }.get(&key) {
+            return ptr;
+        }
+
+        #[cfg(all(feature = "stats", feature = "std"))]
+        let started = Instant::now();
+
+        // accuire value first before borrowing exclusively
+        let value = init();
+
+        // SAFETY: safe to borrow exclusively since no one can borrow more
+        let raw = unsafe { &mut *self.0.get() };
+
+        #[cfg(all(feature = "stats", feature = "std"))]
+        raw.add_compute_time(TypeId::of::<T>(), started.elapsed());
+
+        raw.insert(key, value)
+    }
+
+    /// Get or compute value using key
+    #[inline]
+    pub fn get<T: 'static + Send>(&self, key: impl FnOnce() -> T) -> &T {
+        // SAFETY: pointer is valid and reference cannot outlive more than Self
+        unsafe { self.get_ptr(key).as_ref() }
+    }
+
+    /// Get or compute value using key
+    #[inline]
+    pub fn get_mut<T: 'static + Send>(&mut self, key: impl FnOnce() -> T) -> &mut T {
+        // SAFETY: pointer is valid and reference cannot outlive more than Self
+        unsafe { self.get_ptr(key).as_mut() }
+    }
+
+    /// Get or compute value using key and return copy of it
+    ///
+    /// Map is not borrowed after return, unlike [`Self::get`].
+    #[inline]
+    pub fn get_copy<T: 'static + Send + Copy>(&self, key: impl FnOnce() -> T) -> T {
+        // SAFETY: pointer is valid and value is copied before returning
+        unsafe { *self.get_ptr(key).as_ref() }
+    }
+
+    /// Get or compute value using explicit key and erased initializer
+    ///
+    /// # Safety
+    /// Every value stored using `key` must be type of `T`
+    #[inline]
+    pub unsafe fn get_erased<T: 'static + Send>(
+        &self,
+        key: TypeKey,
+        init: Box<dyn FnOnce() -> T + '_>,
+    ) -> &T {
+        // SAFETY: pointer is valid and reference cannot outlive more than Self
+        unsafe { self.get_ptr_by_key(key, init).as_ref() }
+    }
+
+    /// Keys of stored values in ascending order
+    ///
+    /// Unlike iteration order of the map, the order is reproducible for same set of keys.
+    #[inline]
+    pub fn dump_sorted(&self) -> Vec<TypeKey> {
+        // SAFETY: safe to borrow shared because self is borrowed shared
+        unsafe { &*self.0.get().cast_const() }.dump_sorted()
+    }
+
+    /// Bytes of memory used by stored values since last reset
+    #[inline]
+    pub fn allocated_bytes(&self) -> usize {
+        // SAFETY: safe to borrow shared because self is borrowed shared
+        unsafe { &*self.0.get().cast_const() }.allocated_bytes()
+    }
+
+    /// Write human readable summary of the map
+    ///
+    /// Summary includes number of values and memory usage,
+    /// hit and miss counts with `stats` feature and labeled values with `debug-keys` feature.
+    #[inline]
+    pub fn report(&self, w: &mut impl fmt::Write) -> fmt::Result {
+        // SAFETY: safe to borrow shared because self is borrowed shared
+        unsafe { &*self.0.get().cast_const() }.report(w)
+    }
+
+    /// Bytes which can be allocated before a new chunk is allocated
+    #[inline]
+    pub fn remaining_chunk_capacity(&self) -> usize {
+        // SAFETY: safe to borrow shared because self is borrowed shared
+        unsafe { &*self.0.get().cast_const() }.remaining_chunk_capacity()
+    }
+
+    /// Drop stored values but keep their memory allocated
+    ///
+    /// Without `slab` feature, memory of dropped values is not reused and accumulates until [`Self::reset`] is called.
+    #[inline]
+    pub fn clear(&mut self) {
+        self.0.get_mut().clear();
+    }
+
+    /// Hit and miss counts of the map
+    ///
+    /// Counts are kept across [`Self::reset`].
+    #[cfg(feature = "stats")]
+    #[inline]
+    pub fn stats(&self) -> Stats {
+        // SAFETY: safe to borrow shared because self is borrowed shared
+        unsafe { &*self.0.get().cast_const() }.stats()
+    }
+
+    /// Statistics, number of values and allocated bytes of the map as OpenTelemetry shaped metrics labeled with its name
+    #[cfg(feature = "otel")]
+    #[inline]
+    pub fn export_metrics(&self) -> Vec<Metric> {
+        // SAFETY: safe to borrow shared because self is borrowed shared
+        unsafe { &*self.0.get().cast_const() }.export_metrics()
+    }
+
+    /// Pass every metric of [`Self::export_metrics`] to `f`
+    ///
+    /// Meant to be called from callback of observable instruments registered with a meter.
+    #[cfg(feature = "otel")]
+    #[inline]
+    pub fn register_metrics(&self, f: impl FnMut(&Metric)) {
+        self.export_metrics().iter().for_each(f);
+    }
+
+    /// Cumulative time spent computing values of each type
+    ///
+    /// Only computations of [`Self::get`] and its variants using same path are timed.
+    #[cfg(all(feature = "stats", feature = "std"))]
+    #[inline]
+    pub fn compute_time_by_type(&self) -> Vec<(TypeId, Duration)> {
+        // SAFETY: safe to borrow shared because self is borrowed shared
+        unsafe { &*self.0.get().cast_const() }
+            .compute_time()
+            .to_vec()
+    }
+
+    /// Reset stored values and hit and miss counts
+    #[cfg(feature = "stats")]
+    #[inline]
+    pub fn reset_stats_too(&mut self) {
+        self.reset();
+        self.0.get_mut().reset_stats();
+    }
+
+    /// Reset stored values
+    #[inline]
+    pub fn reset(&mut self) {
+        self.0.get_mut().reset();
+    }
+}
+
+#[derive(Debug, Default)]
+/// Thread safe FnMap implementation.
+///
+/// Uses parking_lot's [`RwLock`] to accuire mutable access to Map.
+///
+/// Stored values can be invalidated lazily using [`ConcurrentFnMap::bump_epoch`].
+///
+/// With `lock-free-read` feature, hits read a snapshot of stored values protected by hazard pointers instead of locking.
+/// Each insert publishes a new snapshot, so it suits maps read much more often than written.
+/// Those hits are counted in [`ConcurrentFnMap::stats`] but not in [`ConcurrentFnMap::entry_stats`].
+pub struct ConcurrentFnMap {
+    raw: RwLock<RawFnMap>,
+    epoch: AtomicU64,
+
+    // keys being computed by get_or_init_once_timeout
+    #[cfg(feature = "std")]
+    computing: Mutex<Vec<TypeKey>>,
+    #[cfg(feature = "std")]
+    computed: Condvar,
+
+    // limit and number of closures computing concurrently
+    #[cfg(feature = "std")]
+    max_computes: Option<usize>,
+    #[cfg(feature = "std")]
+    computes: Mutex<usize>,
+    #[cfg(feature = "std")]
+    compute_slot: Condvar,
+
+    // nanoseconds waited for read and write lock
+    #[cfg(all(feature = "stats", feature = "std"))]
+    read_wait: AtomicU64,
+    #[cfg(all(feature = "stats", feature = "std"))]
+    write_wait: AtomicU64,
+
+    // stored values read without locking and hits counted by them
+    #[cfg(feature = "lock-free-read")]
+    snapshot: hazard::SharedSnapshot,
+    #[cfg(all(feature = "stats", feature = "lock-free-read"))]
+    lock_free_hits: AtomicU64,
+}
+
+impl ConcurrentFnMap {
+    #[inline]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Label map with `name` to distinguish it from other maps
+    #[inline]
+    pub fn with_name(mut self, name: &'static str) -> Self {
+        self.raw.get_mut().set_name(name);
+        self
+    }
+
+    /// Label of the map
+    #[inline]
+    pub fn name(&self) -> Option<&'static str> {
+        self.read_raw().name()
+    }
+
+    /// Allow at most `max` closures computing values at once
+    ///
+    /// Other misses wait until one of computations finishes, while hits are never blocked.
+    /// Values computed inside a computation do not wait, so nested gets cannot deadlock.
+    ///
+    /// # Panics
+    /// Panics if `max` is zero
+    #[cfg(feature = "std")]
+    #[inline]
+    pub fn with_max_concurrent_computes(mut self, max: usize) -> Self {
+        assert!(max > 0, "max must be greater than zero");
+
+        self.max_computes = Some(max);
+        self
+    }
+
+    #[inline]
+    pub fn get_ptr<T: 'static + Send + Sync>(&self, key_fn: impl FnOnce() -> T) -> NonNull<T> {
+        // SAFETY: closure type determines type of the value
+        unsafe { self.get_ptr_by_key(TypeKey::of_val(&key_fn), key_fn) }
+    }
+
+    /// Get or compute value using explicit key
+    ///
+    /// # Safety
+    /// Every value stored using `key` must be type of `T`
+    #[inline]
+    pub unsafe fn get_ptr_by_key<T: 'static + Send + Sync>(
+        &self,
+        key: TypeKey,
+        init: impl FnOnce() -> T,
+    ) -> NonNull<T> {
+        self.add_dependency(key);
+
+        // SAFETY: guaranteed by caller
+        unsafe { self.get_or_compute(key, init) }.0
+    }
+
+    /// Record key is accessed by computation of current thread with `deps` feature
+    #[inline]
+    fn add_dependency(&self, key: TypeKey) {
+        #[cfg(feature = "deps")]
+        if let Some(dependent) = deps::current(self.id()) {
+            self.write_raw().add_dependency(dependent, key);
+        }
+        #[cfg(not(feature = "deps"))]
+        let _ = key;
+    }
+
+    /// Get or compute value using explicit key, limited by [`Self::with_max_concurrent_computes`]
+    ///
+    /// Also returns `true` if the value is computed on this call.
+    ///
+    /// # Safety
+    /// Every value stored using `key` must be type of `T`
+    unsafe fn get_or_compute<T: 'static + Send + Sync>(
+        &self,
+        key: TypeKey,
+        init: impl FnOnce() -> T,
+    ) -> (NonNull<T>, bool) {
+        if let Some(ptr) = self.lookup(&key) {
+            return (ptr, false);
+        }
+
+        #[cfg(feature = "std")]
+        let _permit = ComputePermit::acquire(self);
+
+        // value may be stored while waiting
+        #[cfg(feature = "std")]
+        if let Some(ptr) = self.lookup(&key) {
+            return (ptr, false);
+        }
+
+        #[cfg(feature = "deps")]
+        let _computing = deps::Computing::enter(self.id(), key);
+
+        #[cfg(all(feature = "stats", feature = "std"))]
+        let started = Instant::now();
+
+        let value = init();
+
+        #[cfg(all(feature = "stats", feature = "std"))]
+        self.write_raw()
+            .add_compute_time(TypeId::of::<T>(), started.elapsed());
+
+        (self.store(key, value), true)
+    }
+
+    /// Get or compute value using key and return shared ownership of it
+    ///
+    /// Value is stored in [`Arc`], so returned value stays alive after the map is reset or dropped.
+    #[inline]
+    pub fn get_arc<T: 'static + Send + Sync>(&self, key_fn: impl FnOnce() -> T) -> Arc<T> {
+        // SAFETY: closure type determines type of the value
+        unsafe {
+            self.get_ptr_by_key(arc_key(&key_fn), || Arc::new(key_fn()))
+                .as_ref()
+        }
+        .clone()
+    }
+
+    /// Get or compute value using key
+    ///
+    /// Also returns `true` if the value is computed on this call,
+    /// either because it was missing or invalidated by [`ConcurrentFnMap::bump_epoch`].
+    #[inline]
+    pub fn get_fresh<T: 'static + Send + Sync>(&self, key_fn: impl FnOnce() -> T) -> (&T, bool) {
+        let key = TypeKey::of_val(&key_fn);
+        self.add_dependency(key);
+
+        // SAFETY: closure type determines type of the value
+        let (ptr, fresh) = unsafe { self.get_or_compute(key, key_fn) };
+
+        // SAFETY: pointer is valid and reference cannot outlive more than Self
+        (unsafe { ptr.as_ref() }, fresh)
+    }
+
+    /// Get or compute value using key, computing it at most once at a time
+    ///
+    /// If another thread is computing the value using this method, waits for it instead of computing again.
+    /// Returns `None` if the value is still not computed after `timeout` ticks of `clock` elapsed,
+    /// so the caller can either give up or compute it itself using [`Self::get`].
+    /// Waiters check `clock` every millisecond, since its ticks are not tied to wall time.
+    #[cfg(feature = "std")]
+    pub fn get_or_init_once_timeout<T: 'static + Send + Sync>(
+        &self,
+        key_fn: impl FnOnce() -> T,
+        timeout: u64,
+        clock: &impl Clock,
+    ) -> Option<&T> {
+        const POLL: Duration = Duration::from_millis(1);
+
+        let key = TypeKey::of_val(&key_fn);
+        self.add_dependency(key);
+        let deadline = clock.now().saturating_add(timeout);
+
+        let mut computing = self.computing.lock();
+        loop {
+            if let Some(ptr) = self.lookup::<T>(&key) {
+                // SAFETY: pointer is valid and reference cannot outlive more than Self
+                return Some(unsafe { ptr.as_ref() });
+            }
+
+            if !computing.contains(&key) {
+                break;
+            }
+
+            if clock.now() >= deadline {
+                return None;
+            }
+            self.computed.wait_for(&mut computing, POLL);
+        }
+        computing.push(key);
+        drop(computing);
+
+        let _computing = OnceComputing { map: self, key };
+
+        // SAFETY: closure type determines type of the value
+        let (ptr, _) = unsafe { self.get_or_compute(key, key_fn) };
+
+        // SAFETY: pointer is valid and reference cannot outlive more than Self
+        Some(unsafe { ptr.as_ref() })
+    }
+
+    /// Recorded (dependent, dependency) pairs of keys
+    ///
+    /// A value depends on every values accessed from the map while it is computed.
+    #[cfg(feature = "deps")]
+    #[inline]
+    pub fn dependencies(&self) -> Vec<(TypeKey, TypeKey)> {
+        self.read_raw().dependencies().to_vec()
+    }
+
+    #[cfg(feature = "deps")]
+    fn id(&self) -> *const () {
+        (self as *const Self).cast()
+    }
+
+    /// Get value stored using key if it is not invalidated
+    fn lookup<T: 'static>(&self, key: &TypeKey) -> Option<NonNull<T>> {
+        let epoch = self.epoch.load(Ordering::Acquire);
+
+        #[cfg(feature = "lock-free-read")]
+        if let Some((ptr, _)) = self
+            .snapshot
+            .read(|snapshot| snapshot.get(key))
+            .flatten()
+            .filter(|&(_, stored)| stored >= epoch)
+        {
+            #[cfg(feature = "stats")]
+            self.lock_free_hits.fetch_add(1, Ordering::Relaxed);
+
+            return Some(ptr.cast());
+        }
+
+        let raw = self.read_raw();
+
+        if raw.epoch(key)? < epoch {
+            return None;
+        }
+
+        raw.get(key)
+    }
+
+    /// Lock the map for reading, measuring time waited with `stats` and `std` features
+    #[inline]
+    fn read_raw(&self) -> RwLockReadGuard<'_, RawFnMap> {
+        #[cfg(all(feature = "stats", feature = "std"))]
+        let raw = self.raw.try_read().unwrap_or_else(|| {
+            let started = Instant::now();
+            let raw = self.raw.read();
+
+            self.read_wait
+                .fetch_add(started.elapsed().as_nanos() as u64, Ordering::Relaxed);
+
+            raw
+        });
+        #[cfg(not(all(feature = "stats", feature = "std")))]
+        let raw = self.raw.read();
+
+        #[cfg(all(feature = "stats", feature = "lock-free-read"))]
+        raw.count_hits(self.lock_free_hits.swap(0, Ordering::Relaxed));
+
+        raw
+    }
+
+    /// Publish stored values for reads without locking after exclusive changes
+    #[inline]
+    fn republish(&mut self) {
+        #[cfg(feature = "lock-free-read")]
+        self.snapshot.rebuild(self.raw.get_mut());
+    }
+
+    /// Lock the map for writing, measuring time waited with `stats` and `std` features
+    #[inline]
+    fn write_raw(&self) -> RwLockWriteGuard<'_, RawFnMap> {
+        #[cfg(all(feature = "stats", feature = "std"))]
+        if let Some(raw) = self.raw.try_write() {
+            return raw;
+        }
+
+        #[cfg(all(feature = "stats", feature = "std"))]
+        let started = Instant::now();
+        let raw = self.raw.write();
+
+        #[cfg(all(feature = "stats", feature = "std"))]
+        self.write_wait
+            .fetch_add(started.elapsed().as_nanos() as u64, Ordering::Relaxed);
+
+        raw
+    }
+
+    /// Store value computed in current epoch
+    ///
+    /// If another thread stored valid value first, that value is kept and `value` is dropped,
+    /// so every reference to the key points to same value until invalidated.
+    /// Invalidated value is retired instead of being dropped since it can be still borrowed.
+    fn store<T: 'static>(&self, key: TypeKey, value: T) -> NonNull<T> {
+        let epoch = self.epoch.load(Ordering::Acquire);
+        let mut raw = self.write_raw();
+
+        if raw.epoch(&key).is_some_and(|stored| stored >= epoch) {
+            // SAFETY: value is stored using key so it has type of T
+            let ptr = unsafe { raw.get(&key).unwrap_unchecked() };
+            // value may access the map while dropping
+            drop(raw);
+            drop(value);
+
+            return ptr;
+        }
+
+        raw.retire(&key);
+        let ptr = raw.insert(key, value);
+        raw.set_epoch(&key, epoch);
+
+        #[cfg(feature = "lock-free-read")]
+        // SAFETY: snapshot is published only while write lock is held
+        unsafe {
+            self.snapshot.insert(key, ptr.cast(), epoch)
+        };
+
+        ptr
+    }
+
+    /// Current epoch of the map
+    #[inline]
+    pub fn epoch(&self) -> u64 {
+        self.epoch.load(Ordering::Acquire)
+    }
+
+    /// Invalidate every stored values
+    ///
+    /// Invalidated values are recomputed lazily on next access
+    /// and kept allocated until [`ConcurrentFnMap::reset`] is called.
+    #[inline]
+    pub fn bump_epoch(&self) {
+        self.epoch.fetch_add(1, Ordering::AcqRel);
+    }
+
+    /// Get or compute value using key
+    #[inline]
+    pub fn get<T: 'static + Send + Sync>(&self, key_fn: impl FnOnce() -> T) -> &T {
+        // SAFETY: pointer is valid and reference cannot outlive more than Self
+        unsafe { self.get_ptr(key_fn).as_ref() }
+    }
+
+    /// Get or compute value using key
+    #[inline]
+    pub fn get_mut<T: 'static + Send + Sync>(&mut self, key_fn: impl FnOnce() -> T) -> &mut T {
+        // SAFETY: pointer is valid and reference cannot outlive more than Self
+        unsafe { self.get_ptr(key_fn).as_mut() }
+    }
+
+    /// Get or compute value using key and return copy of it
+    ///
+    /// Map is not borrowed after return, unlike [`Self::get`].
+    #[inline]
+    pub fn get_copy<T: 'static + Send + Sync + Copy>(&self, key_fn: impl FnOnce() -> T) -> T {
+        // SAFETY: pointer is valid and value is copied before returning
+        unsafe { *self.get_ptr(key_fn).as_ref() }
+    }
+
+    /// Get or compute byte buffer using key and return view of its contents
+    #[inline]
+    pub fn get_bytes_view(&self, key_fn: impl FnOnce() -> Vec<u8>) -> &[u8] {
+        self.get(key_fn)
+    }
+
+    /// Get or compute collection using key and iterate its elements
+    #[inline]
+    pub fn get_iter<U: 'static + Send + Sync>(
+        &self,
+        key_fn: impl FnOnce() -> Vec<U>,
+    ) -> slice::Iter<'_, U> {
+        self.get(key_fn).iter()
+    }
+
+    /// Get or compute value using key, labeling the entry with `label`
+    ///
+    /// Label is only recorded with `debug-keys` feature.
+    #[inline]
+    pub fn get_labeled<T: 'static + Send + Sync>(
+        &self,
+        label: &'static str,
+        key_fn: impl FnOnce() -> T,
+    ) -> &T {
+        let key = TypeKey::of_val(&key_fn);
+
+        // SAFETY: closure type determines type of the value
+        let ptr = unsafe { self.get_ptr_by_key(key, key_fn) };
+
+        #[cfg(feature = "debug-keys")]
+        self.write_raw().set_label(&key, label);
+        #[cfg(not(feature = "debug-keys"))]
+        let _ = label;
+
+        // SAFETY: pointer is valid and reference cannot outlive more than Self
+        unsafe { ptr.as_ref() }
+    }
+
+    /// Get or compute value using key, caching it only if `should_cache` returns true
+    ///
+    /// Value not cached is returned as owned.
+    pub fn get_cow<T: 'static + Send + Sync + Clone>(
+        &self,
+        key_fn: impl FnOnce() -> T,
+        should_cache: impl FnOnce(&T) -> bool,
+    ) -> Cow<'_, T> {
+        let key = TypeKey::of_val(&key_fn);
+
+        if let Some(ptr) = self.lookup::<T>(&key) {
+            // SAFETY: pointer is valid and reference cannot outlive more than Self
+            return Cow::Borrowed(unsafe { ptr.as_ref() });
+        }
+
+        let value = key_fn();
+        if !should_cache(&value) {
+            return Cow::Owned(value);
+        }
+
+        let ptr = self.store(key, value);
+
+        // SAFETY: pointer is valid and reference cannot outlive more than Self
+        Cow::Borrowed(unsafe { ptr.as_ref() })
+    }
+
+    /// Get or compute value using explicit key and erased initializer
+    ///
+    /// # Safety
+    /// Every value stored using `key` must be type of `T`
+    #[inline]
+    pub unsafe fn get_erased<T: 'static + Send + Sync>(
+        &self,
+        key: TypeKey,
+        init: Box<dyn FnOnce() -> T + '_>,
+    ) -> &T {
+        // SAFETY: pointer is valid and reference cannot outlive more than Self
+        unsafe { self.get_ptr_by_key(key, init).as_ref() }
+    }
+
+    /// Get or compute values using explicit keys and erased initializers
+    ///
+    /// Repeated keys in one batch are computed once and resolved to same reference.
+    ///
+    /// # Safety
+    /// Every value stored using each key must be type of `T`
+    pub unsafe fn bulk_get<'a, T: 'static + Send + Sync>(
+        &self,
+        entries: impl IntoIterator<Item = (TypeKey, Box<dyn FnOnce() -> T + 'a>)>,
+    ) -> Vec<&T> {
+        let mut resolved = HashMap::<TypeKey, NonNull<T>, BuildNoHashHasher<u64>>::default();
+
+        entries
+            .into_iter()
+            .map(|(key, init)| {
+                let ptr = *resolved
+                    .entry(key)
+                    // SAFETY: guaranteed by caller
+                    .or_insert_with(|| unsafe { self.get_ptr_by_key(key, init) });
+
+                // SAFETY: pointer is valid and reference cannot outlive more than Self
+                unsafe { ptr.as_ref() }
+            })
+            .collect()
+    }
+
+    /// Call `f` with label and key of every stored values
+    #[cfg(feature = "debug-keys")]
+    #[inline]
+    pub fn dump(&self, f: impl FnMut(&'static str, TypeKey)) {
+        self.read_raw().dump(f);
+    }
+
+    /// Keys of stored values in ascending order
+    ///
+    /// Unlike iteration order of the map, the order is reproducible for same set of keys.
+    #[inline]
+    pub fn dump_sorted(&self) -> Vec<TypeKey> {
+        self.read_raw().dump_sorted()
+    }
+
+    /// Bytes of memory used by stored values since last reset
+    #[inline]
+    pub fn allocated_bytes(&self) -> usize {
+        self.read_raw().allocated_bytes()
+    }
+
+    /// Write human readable summary of the map
+    ///
+    /// Summary includes number of values and memory usage,
+    /// hit and miss counts with `stats` feature and labeled values with `debug-keys` feature.
+    #[inline]
+    pub fn report(&self, w: &mut impl fmt::Write) -> fmt::Result {
+        self.read_raw().report(w)
+    }
+
+    /// Bytes which can be allocated before a new chunk is allocated
+    #[inline]
+    pub fn remaining_chunk_capacity(&self) -> usize {
+        self.read_raw().remaining_chunk_capacity()
+    }
+
+    /// Remove and drop value stored using key
+    ///
+    /// Returns `true` if the value was stored.
+    #[inline]
+    pub fn remove<T: 'static + Send + Sync>(&mut self, key_fn: impl FnOnce() -> T) -> bool {
+        let removed = self.raw.get_mut().remove(&TypeKey::of_val(&key_fn));
+        self.republish();
+        removed
+    }
+
+    /// Remove and drop every values of type `T`
+    #[inline]
+    pub fn invalidate_type<T: 'static>(&mut self) {
+        self.raw.get_mut().remove_type(&TypeKey::of::<T>());
+        self.republish();
+    }
+
+    /// Remove value stored using key and every values depending on it
+    ///
+    /// Dependents are found transitively from [`Self::dependencies`].
+    #[cfg(feature = "deps")]
+    #[inline]
+    pub fn invalidate_cascade<T: 'static + Send + Sync>(&mut self, key_fn: impl FnOnce() -> T) {
+        self.raw.get_mut().remove_cascade(&TypeKey::of_val(&key_fn));
+        self.republish();
+    }
+
+    /// Drop stored values but keep their memory allocated
+    ///
+    /// Without `slab` feature, memory of dropped values is not reused and accumulates until [`Self::reset`] is called.
+    #[inline]
+    pub fn clear(&mut self) {
+        self.raw.get_mut().clear();
+        self.republish();
+    }
+
+    /// Hit and miss counts of the map
+    ///
+    /// Counts are kept across [`Self::reset`].
+    #[cfg(feature = "stats")]
+    #[inline]
+    pub fn stats(&self) -> Stats {
+        self.read_raw().stats()
+    }
+
+    /// Statistics, number of values and allocated bytes of the map as OpenTelemetry shaped metrics labeled with its name
+    #[cfg(feature = "otel")]
+    #[inline]
+    pub fn export_metrics(&self) -> Vec<Metric> {
+        self.read_raw().export_metrics()
+    }
+
+    /// Pass every metric of [`Self::export_metrics`] to `f`
+    ///
+    /// Meant to be called from callback of observable instruments registered with a meter.
+    #[cfg(feature = "otel")]
+    #[inline]
+    pub fn register_metrics(&self, f: impl FnMut(&Metric)) {
+        self.export_metrics().iter().for_each(f);
+    }
+
+    /// Read statistics of value stored using key
+    #[cfg(feature = "stats")]
+    #[inline]
+    pub fn entry_stats<T: 'static>(&self, key_fn: impl FnOnce() -> T) -> Option<EntryStats> {
+        self.read_raw().entry_stats(&TypeKey::of_val(&key_fn))
+    }
+
+    /// Cumulative time spent computing values of each type
+    ///
+    /// Only computations of [`Self::get`] and its variants using same path are timed.
+    #[cfg(all(feature = "stats", feature = "std"))]
+    #[inline]
+    pub fn compute_time_by_type(&self) -> Vec<(TypeId, Duration)> {
+        self.read_raw().compute_time().to_vec()
+    }
+
+    /// Total time spent waiting for lock of the map
+    #[cfg(all(feature = "stats", feature = "std"))]
+    #[inline]
+    pub fn lock_stats(&self) -> LockStats {
+        LockStats {
+            total_read_wait: Duration::from_nanos(self.read_wait.load(Ordering::Relaxed)),
+            total_write_wait: Duration::from_nanos(self.write_wait.load(Ordering::Relaxed)),
+        }
+    }
+
+    /// Reset stored values and hit and miss counts
+    #[cfg(feature = "stats")]
+    #[inline]
+    pub fn reset_stats_too(&mut self) {
+        self.reset();
+        self.raw.get_mut().reset_stats();
+
+        #[cfg(feature = "lock-free-read")]
+        self.lock_free_hits.store(0, Ordering::Relaxed);
+    }
+
+    /// Reset stored values
+    #[inline]
+    pub fn reset(&mut self) {
+        self.raw.get_mut().reset();
+        self.republish();
+    }
+}
+
+/// Wakes waiters on drop, even if computation panics
+#[cfg(feature = "std")]
+struct OnceComputing<'a> {
+    map: &'a ConcurrentFnMap,
+    key: TypeKey,
+}
+
+#[cfg(feature = "std")]
+impl Drop for OnceComputing<'_> {
+    fn drop(&mut self) {
+        self.map.computing.lock().retain(|key| *key != self.key);
+        self.map.computed.notify_all();
+    }
+}
+
+#[cfg(feature = "std")]
+std::thread_local! {
+    // maps current thread holds compute permit of
+    static PERMITS: core::cell::RefCell<Vec<*const ConcurrentFnMap>> = const { core::cell::RefCell::new(Vec::new()) };
+}
+
+/// Compute slot of [`ConcurrentFnMap`] with limited concurrent computations, released on drop
+#[cfg(feature = "std")]
+struct ComputePermit<'a> {
+    map: &'a ConcurrentFnMap,
+}
+
+#[cfg(feature = "std")]
+impl<'a> ComputePermit<'a> {
+    /// Wait for a free compute slot
+    ///
+    /// Returns `None` if computations are not limited or current thread already holds a slot.
+    fn acquire(map: &'a ConcurrentFnMap) -> Option<Self> {
+        let max = map.max_computes?;
+        let id = map as *const ConcurrentFnMap;
+
+        if PERMITS.with_borrow(|permits| permits.contains(&id)) {
+            return None;
+        }
+
+        let mut computes = map.computes.lock();
+        while *computes >= max {
+            map.compute_slot.wait(&mut computes);
+        }
+        *computes += 1;
+        drop(computes);
+
+        PERMITS.with_borrow_mut(|permits| permits.push(id));
+        Some(Self { map })
+    }
+}
+
+#[cfg(feature = "std")]
+impl Drop for ComputePermit<'_> {
+    fn drop(&mut self) {
+        let id = self.map as *const ConcurrentFnMap;
+        PERMITS.with_borrow_mut(|permits| permits.retain(|permit| *permit != id));
+
+        *self.map.computes.lock() -= 1;
+        self.map.compute_slot.notify_one();
+    }
+}
+
+#[cfg(not(feature = "no-unsafe-marker"))]
+unsafe impl Send for ConcurrentFnMap {}
+#[cfg(not(feature = "no-unsafe-marker"))]
+unsafe impl Sync for ConcurrentFnMap {}
+
+#[cfg(test)]
+mod tests {
+    use crate::LocalOnlyFnMap;
+
+    use super::{ConcurrentFnMap, FnMap};
+
+    #[cfg(not(feature = "no-unsafe-marker"))]
+    #[test]
+    fn test_trait() {
+        const fn is_send<T: Send>() {}
+        const fn is_sync<T: Sync>() {}
+
+        is_send::<FnMap>();
+
+        is_send::<ConcurrentFnMap>();
+        is_sync::<ConcurrentFnMap>();
+    }
+
+    #[test]
+    fn test_local() {
+        let map = FnMap::new();
+
+        fn one() -> i32 {
+            1
+        }
+
+        let b = map.get(|| map.get(one) + 1);
+        let a = map.get(one);
+
+        assert_eq!(*b, 2);
+        assert_eq!(*a, 1);
+    }
+
+    #[test]
+    fn test_local_only() {
+        let map = LocalOnlyFnMap::new();
+
+        fn one() -> i32 {
+            1
+        }
+
+        let b = map.get(|| map.get(one) + 1);
+        let a = map.get(one);
+
+        assert_eq!(*b, 2);
+        assert_eq!(*a, 1);
+    }
+
+    #[test]
+    fn test_atomic() {
+        let map = ConcurrentFnMap::new();
+
+        fn one() -> i32 {
+            1
+        }
+
+        let b = map.get(|| map.get(one) + 1);
+        let a = map.get(one);
 
         assert_eq!(*b, 2);
         assert_eq!(*a, 1);
     }
+
+    #[test]
+    fn test_erased() {
+        use alloc::boxed::Box;
+        use type_key::TypeKey;
+
+        struct A;
+        struct B;
+
+        let registry: [(TypeKey, Box<dyn FnOnce() -> i32>); 2] = [
+            (TypeKey::of::<A>(), Box::new(|| 1)),
+            (TypeKey::of::<B>(), Box::new(|| 2)),
+        ];
+
+        let map = FnMap::new();
+        for (key, init) in registry {
+            // SAFETY: every key is used with i32
+            unsafe { map.get_erased(key, init) };
+        }
+
+        // SAFETY: every key is used with i32
+        unsafe {
+            assert_eq!(
+                *map.get_erased::<i32>(TypeKey::of::<A>(), Box::new(|| 0)),
+                1
+            );
+            assert_eq!(
+                *map.get_erased::<i32>(TypeKey::of::<B>(), Box::new(|| 0)),
+                2
+            );
+        }
+    }
+
+    #[test]
+    fn test_bulk_dedup() {
+        use alloc::boxed::Box;
+        use core::cell::Cell;
+        use type_key::TypeKey;
+
+        struct A;
+        struct B;
+
+        let computed = Cell::new(0);
+        let init = || -> Box<dyn FnOnce() -> i32 + '_> {
+            Box::new(|| {
+                computed.set(computed.get() + 1);
+                computed.get()
+            })
+        };
+
+        let map = FnMap::new();
+        // SAFETY: every key is used with i32
+        let values = unsafe {
+            map.bulk_get([
+                (TypeKey::of::<A>(), init()),
+                (TypeKey::of::<B>(), init()),
+                (TypeKey::of::<A>(), init()),
+            ])
+        };
+
+        assert_eq!(computed.get(), 2);
+        assert_eq!(*values[0], 1);
+        assert_eq!(*values[1], 2);
+        assert!(core::ptr::eq(values[0], values[2]));
+    }
+
+    #[test]
+    fn test_bytes_view() {
+        use alloc::{vec, vec::Vec};
+
+        let mut map = FnMap::new();
+
+        fn bytes() -> Vec<u8> {
+            vec![1, 2, 3]
+        }
+
+        assert_eq!(map.get_bytes_view(bytes), &[1, 2, 3]);
+
+        map.get_mut(bytes).push(4);
+        assert_eq!(map.get_bytes_view(bytes), &[1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn test_name() {
+        assert_eq!(FnMap::new().name(), None);
+
+        let map = FnMap::new().with_name("config");
+        assert_eq!(map.name(), Some("config"));
+
+        let map = ConcurrentFnMap::new().with_name("shared");
+        assert_eq!(map.name(), Some("shared"));
+
+        // report and metrics carry the name
+        let mut report = alloc::string::String::new();
+        map.report(&mut report).unwrap();
+        assert!(report.starts_with("map `shared`\n"));
+
+        #[cfg(feature = "otel")]
+        assert!(map
+            .export_metrics()
+            .iter()
+            .all(|metric| metric.map_name == Some("shared")));
+    }
+
+    #[test]
+    fn test_emplace() {
+        extern crate std;
+
+        const SIZE: usize = 1_000_000;
+
+        // value cannot fit in the stack of thread
+        std::thread::Builder::new()
+            .stack_size(64 * 1024)
+            .spawn(|| {
+                let map = FnMap::new();
+
+                let key = || -> [u8; SIZE] { unreachable!() };
+                // SAFETY: value is fully initialized
+                let value = unsafe {
+                    map.get_emplace(key, |value| {
+                        value.as_mut_ptr().cast::<u8>().write_bytes(1, SIZE);
+                    })
+                };
+
+                assert!(value.iter().all(|&byte| byte == 1));
+            })
+            .unwrap()
+            .join()
+            .unwrap();
+    }
+
+    #[test]
+    fn test_clear() {
+        use core::sync::atomic::{AtomicUsize, Ordering};
+
+        static DROPPED: AtomicUsize = AtomicUsize::new(0);
+
+        struct Dropped;
+        impl Drop for Dropped {
+            fn drop(&mut self) {
+                DROPPED.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+
+        let mut map = LocalOnlyFnMap::new();
+        assert_eq!(map.allocated_bytes(), 0);
+
+        fn one() -> i32 {
+            1
+        }
+        map.get(one);
+        map.get(|| Dropped);
+        let allocated = map.allocated_bytes();
+        assert!(allocated > 0);
+
+        map.clear();
+        assert_eq!(DROPPED.load(Ordering::Relaxed), 1);
+        assert_eq!(map.allocated_bytes(), allocated);
+
+        map.get(one);
+        map.reset();
+        assert_eq!(map.allocated_bytes(), 0);
+    }
+
+    #[test]
+    fn test_cow() {
+        use alloc::borrow::Cow;
+
+        let map = FnMap::new();
+
+        fn one() -> i32 {
+            1
+        }
+
+        assert!(matches!(map.get_cow(one, |_| false), Cow::Owned(1)));
+        assert!(matches!(map.get_cow(one, |_| true), Cow::Borrowed(1)));
+        assert!(matches!(map.get_cow(one, |_| false), Cow::Borrowed(1)));
+    }
+
+    #[test]
+    #[cfg(feature = "debug-keys")]
+    fn test_labeled() {
+        use alloc::vec::Vec;
+        use type_key::TypeKey;
+
+        let map = FnMap::new();
+
+        fn one() -> i32 {
+            1
+        }
+        fn two() -> i32 {
+            2
+        }
+
+        map.get_labeled("one", one);
+        map.get_labeled("two", two);
+        map.get(|| 3);
+
+        let mut labels = Vec::new();
+        map.dump(|label, key| labels.push((label, key)));
+        labels.sort();
+
+        assert_eq!(labels.len(), 3);
+        assert_eq!(labels[0].0, "");
+        assert!(labels.contains(&("one", TypeKey::of_val(&one))));
+        assert!(labels.contains(&("two", TypeKey::of_val(&two))));
+    }
+
+    #[test]
+    fn test_versioned() {
+        let mut map = FnMap::new();
+        let mut computed = 0;
+
+        let mut get = |map: &mut FnMap, version| {
+            *map.get_versioned(
+                || {
+                    computed += 1;
+                    computed
+                },
+                version,
+            )
+        };
+
+        assert_eq!(get(&mut map, 1), 1);
+        assert_eq!(get(&mut map, 1), 1);
+        assert_eq!(get(&mut map, 2), 2);
+        assert_eq!(get(&mut map, 1), 2);
+    }
+
+    #[cfg(not(feature = "no-unsafe-marker"))]
+    #[test]
+    fn test_frozen() {
+        extern crate std;
+
+        fn one() -> i32 {
+            1
+        }
+        fn two() -> i32 {
+            2
+        }
+
+        let map = FnMap::new();
+        map.get(one);
+        let frozen = map.into_frozen_arc();
+
+        let threads: [_; 4] = core::array::from_fn(|_| {
+            let frozen = frozen.clone();
+            std::thread::spawn(move || {
+                assert_eq!(frozen.get(one), Some(&1));
+                assert_eq!(frozen.get(two), None);
+            })
+        });
+
+        for thread in threads {
+            thread.join().unwrap();
+        }
+    }
+
+    #[test]
+    fn test_epoch() {
+        use core::sync::atomic::{AtomicU64, Ordering};
+
+        let map = ConcurrentFnMap::new();
+        let a_computed = AtomicU64::new(0);
+        let b_computed = AtomicU64::new(0);
+
+        let a = || a_computed.fetch_add(1, Ordering::Relaxed) + 1;
+        let b = || b_computed.fetch_add(1, Ordering::Relaxed) + 1;
+
+        assert_eq!(*map.get(a), 1);
+        assert_eq!(*map.get(b), 1);
+
+        map.bump_epoch();
+        assert_eq!(*map.get(a), 2);
+        assert_eq!(*map.get(a), 2);
+        assert_eq!(b_computed.load(Ordering::Relaxed), 1);
+
+        assert_eq!(*map.get(b), 2);
+    }
+
+    #[test]
+    fn test_fresh() {
+        let map = ConcurrentFnMap::new();
+
+        fn one() -> i32 {
+            1
+        }
+
+        assert_eq!(map.get_fresh(one), (&1, true));
+        assert_eq!(map.get_fresh(one), (&1, false));
+
+        map.bump_epoch();
+        assert_eq!(map.get_fresh(one), (&1, true));
+        assert_eq!(map.get_fresh(one), (&1, false));
+    }
+
+    #[test]
+    #[cfg(feature = "stats")]
+    fn test_stats() {
+        use crate::stats::Stats;
+
+        let mut map = FnMap::new();
+
+        fn one() -> i32 {
+            1
+        }
+
+        map.get(one);
+        map.get(one);
+        assert_eq!(
+            map.stats(),
+            Stats {
+                hits: 1,
+                misses: 1,
+                evictions: 0
+            }
+        );
+
+        map.reset();
+        assert_eq!(
+            map.stats(),
+            Stats {
+                hits: 1,
+                misses: 1,
+                evictions: 0
+            }
+        );
+
+        map.reset_stats_too();
+        assert_eq!(map.stats(), Stats::default());
+    }
+
+    #[test]
+    fn test_variant() {
+        let map = FnMap::new();
+        let mut computed = 0;
+
+        let mut value = || {
+            computed += 1;
+            computed
+        };
+
+        assert_eq!(*map.get_variant::<0, _>(&mut value), 1);
+        assert_eq!(*map.get_variant::<1, _>(&mut value), 2);
+        assert_eq!(*map.get_variant::<0, _>(&mut value), 1);
+        assert_eq!(*map.get_variant::<1, _>(&mut value), 2);
+    }
+
+    #[test]
+    fn test_last_accessed() {
+        let mut map = FnMap::new();
+        let mut computed = 0;
+
+        fn one() -> i32 {
+            1
+        }
+
+        assert_eq!(*map.get(one), 1);
+        assert_eq!(*map.get(one), 1);
+        assert_eq!(*map.get(|| 2), 2);
+        assert_eq!(*map.get(one), 1);
+
+        let mut value = || {
+            computed += 1;
+            computed
+        };
+        assert_eq!(*map.get(&mut value), 1);
+        assert_eq!(*map.get(&mut value), 1);
+
+        map.reset();
+        assert_eq!(*map.get(&mut value), 2);
+
+        // hits served from last accessed value are counted
+        #[cfg(feature = "stats")]
+        {
+            let hits = map.stats().hits;
+            map.get(one);
+            map.get(one);
+            map.get(one);
+            assert_eq!(map.stats().hits, hits + 2);
+            assert_eq!(map.entry_stats(one).unwrap().reads, 2);
+
+            map.get(one);
+            map.get(|| 2);
+            assert_eq!(map.stats().hits, hits + 3);
+            assert_eq!(map.entry_stats(one).unwrap().reads, 3);
+        }
+    }
+
+    #[test]
+    fn test_pin() {
+        use core::marker::PhantomPinned;
+
+        let mut map = FnMap::new();
+
+        struct Pinned(i32, PhantomPinned);
+
+        fn pinned() -> Pinned {
+            Pinned(1, PhantomPinned)
+        }
+
+        let first: *const Pinned = &*map.get_pin_mut(pinned);
+        map.get(|| 1);
+        let second = map.get_pin_mut(pinned);
+
+        assert_eq!(second.0, 1);
+        assert_eq!(first, &*second as *const Pinned);
+    }
+
+    #[test]
+    fn test_remove() {
+        let mut map = FnMap::new();
+
+        fn one() -> i32 {
+            1
+        }
+        fn two() -> i32 {
+            2
+        }
+
+        map.get(one);
+        map.get(two);
+        let allocated = map.allocated_bytes();
+
+        assert!(map.remove(one));
+        assert!(!map.remove(one));
+        assert_eq!(*map.get(two), 2);
+
+        map.get(one);
+        #[cfg(feature = "slab")]
+        assert_eq!(map.allocated_bytes(), allocated);
+        #[cfg(not(feature = "slab"))]
+        assert!(map.allocated_bytes() > allocated);
+    }
+
+    #[test]
+    #[cfg(feature = "deps")]
+    fn test_dependencies() {
+        use type_key::TypeKey;
+
+        let map = FnMap::new();
+
+        fn one() -> i32 {
+            1
+        }
+        let two = || map.get(one) + 1;
+
+        assert_eq!(*map.get(two), 2);
+        assert_eq!(
+            map.dependencies(),
+            [(TypeKey::of_val(&two), TypeKey::of_val(&one))]
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "deps")]
+    fn test_invalidate_cascade() {
+        use core::sync::atomic::{AtomicI32, Ordering};
+
+        static COMPUTED: AtomicI32 = AtomicI32::new(0);
+
+        let mut map = FnMap::new();
+
+        fn one() -> i32 {
+            COMPUTED.fetch_add(1, Ordering::Relaxed);
+            1
+        }
+        fn two(map: &FnMap) -> i32 {
+            *map.get(|| map.get(one) + 1)
+        }
+        fn three() -> i32 {
+            3
+        }
+
+        assert_eq!(two(&map), 2);
+        map.get(three);
+        assert_eq!(COMPUTED.load(Ordering::Relaxed), 1);
+
+        map.invalidate_cascade(one);
+        assert_eq!(map.dependencies(), []);
+
+        assert_eq!(two(&map), 2);
+        assert_eq!(COMPUTED.load(Ordering::Relaxed), 2);
+        assert!(map.remove(three));
+    }
+
+    #[test]
+    fn test_weak() {
+        let mut map = FnMap::new();
+        let other = FnMap::new();
+
+        fn one() -> i32 {
+            1
+        }
+
+        let weak = map.get_weak(one);
+        map.get(|| 2);
+        assert_eq!(weak.upgrade(&map), Some(&1));
+        assert_eq!(weak.upgrade(&other), None);
+
+        map.reset();
+        assert_eq!(weak.upgrade(&map), None);
+    }
+
+    #[test]
+    fn test_try_emplace() {
+        let map = FnMap::new();
+
+        let key = || -> i32 { unreachable!() };
+
+        // SAFETY: value is not initialized on failure
+        let res = unsafe { map.try_get_emplace(key, |_| Err("failed")) };
+        assert_eq!(res, Err("failed"));
+
+        // SAFETY: value is initialized on success
+        let res = unsafe {
+            map.try_get_emplace(key, |value| {
+                value.write(1);
+                Ok::<_, ()>(())
+            })
+        };
+        assert_eq!(res, Ok(&1));
+
+        // SAFETY: value is already stored
+        let res = unsafe { map.try_get_emplace(key, |_| Err("failed")) };
+        assert_eq!(res, Ok(&1));
+    }
+
+    #[test]
+    fn test_copy() {
+        let mut map = FnMap::new();
+        let mut concurrent = ConcurrentFnMap::new();
+
+        let value = map.get_copy(|| 1_i32);
+        map.reset();
+        assert_eq!(value, 1);
+
+        let value = concurrent.get_copy(|| 2_i32);
+        concurrent.reset();
+        assert_eq!(value, 2);
+    }
+
+    #[test]
+    fn test_fn() {
+        use alloc::boxed::Box;
+        use core::sync::atomic::{AtomicUsize, Ordering};
+
+        static BUILT: AtomicUsize = AtomicUsize::new(0);
+
+        let map = FnMap::new();
+        let make = || -> Box<dyn Fn(i32) -> i32 + Send> {
+            BUILT.fetch_add(1, Ordering::Relaxed);
+            let offset = alloc::vec![1, 2, 3].iter().sum::<i32>();
+
+            Box::new(move |x| x + offset)
+        };
+
+        assert_eq!(map.get_fn(make)(1), 7);
+        assert_eq!(map.get_fn(make)(2), 8);
+        assert_eq!(BUILT.load(Ordering::Relaxed), 1);
+    }
+
+    #[cfg(all(feature = "std", not(feature = "no-unsafe-marker")))]
+    #[test]
+    fn test_once_timeout() {
+        extern crate std;
+
+        use crate::clock::SystemClock;
+        use core::{
+            sync::atomic::{AtomicBool, AtomicUsize, Ordering},
+            time::Duration,
+        };
+        use std::thread;
+
+        static STARTED: AtomicBool = AtomicBool::new(false);
+        static COMPUTED: AtomicUsize = AtomicUsize::new(0);
+
+        let map = ConcurrentFnMap::new();
+        let slow = || {
+            STARTED.store(true, Ordering::Release);
+            COMPUTED.fetch_add(1, Ordering::Relaxed);
+            thread::sleep(Duration::from_millis(200));
+            1
+        };
+
+        let clock = SystemClock::new();
+        thread::scope(|scope| {
+            let computing = scope.spawn(|| map.get_or_init_once_timeout(slow, 10_000, &clock));
+
+            while !STARTED.load(Ordering::Acquire) {
+                thread::yield_now();
+            }
+            assert_eq!(map.get_or_init_once_timeout(slow, 10, &clock), None);
+
+            assert_eq!(computing.join().unwrap(), Some(&1));
+        });
+
+        assert_eq!(map.get_or_init_once_timeout(slow, 0, &clock), Some(&1));
+        assert_eq!(COMPUTED.load(Ordering::Relaxed), 1);
+    }
+
+    #[cfg(all(feature = "stats", feature = "std", not(feature = "no-unsafe-marker")))]
+    #[test]
+    fn test_lock_wait() {
+        extern crate std;
+
+        use core::{
+            sync::atomic::{AtomicBool, Ordering},
+            time::Duration,
+        };
+        use std::thread;
+
+        let map = ConcurrentFnMap::new();
+        let locked = AtomicBool::new(false);
+        assert_eq!(map.lock_stats().total_read_wait, Duration::ZERO);
+
+        thread::scope(|scope| {
+            let raw = map.raw.write();
+
+            scope.spawn(|| {
+                locked.store(true, Ordering::Release);
+                map.get(|| 1);
+            });
+
+            while !locked.load(Ordering::Acquire) {
+                thread::yield_now();
+            }
+            thread::sleep(Duration::from_millis(20));
+            drop(raw);
+        });
+
+        assert!(map.lock_stats().total_read_wait >= Duration::from_millis(10));
+
+        // recording dependency waits for write lock
+        #[cfg(feature = "deps")]
+        {
+            let computing = AtomicBool::new(false);
+            locked.store(false, Ordering::Release);
+
+            thread::scope(|scope| {
+                scope.spawn(|| {
+                    map.get(|| {
+                        computing.store(true, Ordering::Release);
+                        while !locked.load(Ordering::Acquire) {
+                            thread::yield_now();
+                        }
+
+                        *map.get(|| 2) + 1
+                    });
+                });
+
+                while !computing.load(Ordering::Acquire) {
+                    thread::yield_now();
+                }
+                let raw = map.raw.write();
+                locked.store(true, Ordering::Release);
+
+                thread::sleep(Duration::from_millis(20));
+                drop(raw);
+            });
+
+            assert!(map.lock_stats().total_write_wait >= Duration::from_millis(10));
+        }
+    }
+
+    #[cfg(all(feature = "std", not(feature = "no-unsafe-marker")))]
+    #[test]
+    fn test_max_concurrent_computes() {
+        extern crate std;
+
+        use core::{
+            sync::atomic::{AtomicBool, AtomicUsize, Ordering},
+            time::Duration,
+        };
+        use std::thread;
+
+        static RUNNING: AtomicUsize = AtomicUsize::new(0);
+        static MAX_RUNNING: AtomicUsize = AtomicUsize::new(0);
+        static HIT: AtomicBool = AtomicBool::new(false);
+
+        fn compute(n: u32) -> u32 {
+            let running = RUNNING.fetch_add(1, Ordering::SeqCst) + 1;
+            MAX_RUNNING.fetch_max(running, Ordering::SeqCst);
+
+            // hits are served while computing
+            while !HIT.load(Ordering::Acquire) {
+                thread::yield_now();
+            }
+            thread::sleep(Duration::from_millis(10));
+
+            RUNNING.fetch_sub(1, Ordering::SeqCst);
+            n
+        }
+
+        let map = ConcurrentFnMap::new().with_max_concurrent_computes(1);
+        let stored = || 0;
+        map.get(stored);
+
+        thread::scope(|scope| {
+            let computing = [
+                scope.spawn(|| *map.get(|| compute(1))),
+                scope.spawn(|| *map.get(|| compute(2))),
+                scope.spawn(|| *map.get(|| compute(3))),
+                // nested computation does not wait for the slot held by itself
+                scope.spawn(|| *map.get(|| compute(4) + *map.get(|| 5))),
+            ];
+
+            while RUNNING.load(Ordering::SeqCst) == 0 {
+                thread::yield_now();
+            }
+            assert_eq!(*map.get(stored), 0);
+            HIT.store(true, Ordering::Release);
+
+            let results = computing.map(|handle| handle.join().unwrap());
+            assert_eq!(results, [1, 2, 3, 9]);
+        });
+
+        assert_eq!(MAX_RUNNING.load(Ordering::SeqCst), 1);
+    }
+
+    #[cfg(not(feature = "no-unsafe-marker"))]
+    #[test]
+    fn test_first_insert_wins() {
+        extern crate std;
+
+        use std::{sync::Barrier, thread, vec::Vec};
+
+        let map = ConcurrentFnMap::new();
+        let barrier = Barrier::new(8);
+
+        fn one() -> i32 {
+            1
+        }
+
+        let addresses = thread::scope(|scope| {
+            let threads = (0..8)
+                .map(|_| {
+                    scope.spawn(|| {
+                        barrier.wait();
+
+                        let first = map.get(one) as *const i32;
+                        for _ in 0..100 {
+                            assert_eq!(map.get(one) as *const i32, first);
+                        }
+
+                        first as usize
+                    })
+                })
+                .collect::<Vec<_>>();
+
+            threads
+                .into_iter()
+                .map(|thread| thread.join().unwrap())
+                .collect::<Vec<_>>()
+        });
+
+        assert!(addresses.iter().all(|address| *address == addresses[0]));
+    }
+
+    #[test]
+    fn test_retry() {
+        use core::sync::atomic::{AtomicUsize, Ordering};
+
+        use crate::clock::ManualClock;
+
+        static CALLS: AtomicUsize = AtomicUsize::new(0);
+
+        let map = FnMap::new();
+        let clock = ManualClock::new();
+        let compute = || match CALLS.fetch_add(1, Ordering::Relaxed) {
+            0 => Err("failed"),
+            _ => Ok(1),
+        };
+
+        assert_eq!(map.get_with_retry(compute, 50, &clock), Err(&"failed"));
+        clock.advance(49);
+        assert_eq!(map.get_with_retry(compute, 50, &clock), Err(&"failed"));
+        assert_eq!(CALLS.load(Ordering::Relaxed), 1);
+
+        clock.advance(1);
+        assert_eq!(map.get_with_retry(compute, 50, &clock), Ok(&1));
+        assert_eq!(map.get_with_retry(compute, 50, &clock), Ok(&1));
+        assert_eq!(CALLS.load(Ordering::Relaxed), 2);
+    }
+
+    #[test]
+    fn test_ttl() {
+        use core::cell::Cell;
+
+        use crate::clock::ManualClock;
+
+        let map = FnMap::new();
+        let clock = ManualClock::new();
+
+        let computed = Cell::new(0);
+        let value = || {
+            computed.set(computed.get() + 1);
+            computed.get()
+        };
+
+        assert_eq!(*map.get_with_ttl(value, 10, &clock), 1);
+        clock.advance(9);
+        assert_eq!(*map.get_with_ttl(value, 10, &clock), 1);
+
+        clock.advance(1);
+        assert_eq!(*map.get_with_ttl(value, 10, &clock), 2);
+        clock.advance(9);
+        assert_eq!(*map.get_with_ttl(value, 10, &clock), 2);
+        assert_eq!(computed.get(), 2);
+    }
+
+    #[test]
+    fn test_loader() {
+        use core::cell::Cell;
+
+        use crate::loader::Loader;
+
+        struct One;
+        impl Loader<i32> for One {
+            fn load(&self) -> i32 {
+                1
+            }
+        }
+
+        struct Mock(Cell<usize>);
+        impl Loader<i32> for Mock {
+            fn load(&self) -> i32 {
+                self.0.set(self.0.get() + 1);
+                2
+            }
+        }
+
+        let map = FnMap::new();
+        let mock = Mock(Cell::new(0));
+
+        assert_eq!(*map.get_via(&One), 1);
+        assert_eq!(*map.get_via(&mock), 2);
+        assert_eq!(*map.get_via(&mock), 2);
+        assert_eq!(*map.get_via(&One), 1);
+        assert_eq!(mock.0.get(), 1);
+    }
+
+    #[cfg(all(feature = "stats", feature = "std"))]
+    #[test]
+    fn test_compute_time() {
+        extern crate std;
+
+        use core::{any::TypeId, time::Duration};
+
+        let map = FnMap::new();
+
+        map.get(|| {
+            std::thread::sleep(Duration::from_millis(20));
+            1_i32
+        });
+        map.get(|| 1_u8);
+
+        let times = map.compute_time_by_type();
+        let time_of = |ty| times.iter().find(|(stored, _)| *stored == ty).unwrap().1;
+
+        assert!(time_of(TypeId::of::<i32>()) >= Duration::from_millis(20));
+        assert!(time_of(TypeId::of::<u8>()) < time_of(TypeId::of::<i32>()));
+    }
+
+    #[test]
+    fn test_invalidate_type() {
+        use alloc::string::String;
+
+        let mut map = FnMap::new();
+
+        let a = || String::from("a");
+        let b = || String::from("b");
+        let one = || 1_i32;
+        let two = || 2_i32;
+
+        map.get(a);
+        map.get(b);
+        map.get(one);
+        map.get(two);
+
+        map.invalidate_type::<String>();
+
+        assert!(!map.remove(a));
+        assert!(!map.remove(b));
+        assert!(map.remove(one));
+        assert!(map.remove(two));
+    }
+
+    #[test]
+    fn test_arc() {
+        use alloc::sync::Arc;
+
+        let mut map = ConcurrentFnMap::new();
+        let value = || 1;
+
+        let arc = map.get_arc(value);
+        assert!(Arc::ptr_eq(&arc, &map.get_arc(value)));
+
+        map.reset();
+        assert_eq!(*arc, 1);
+        assert_eq!(Arc::strong_count(&arc), 1);
+    }
+
+    #[test]
+    fn test_get_mut() {
+        let mut map = FnMap::new();
+        let mut concurrent = ConcurrentFnMap::new();
+
+        fn one() -> i32 {
+            1
+        }
+
+        let inner = *map.get(one);
+        *map.get_mut(|| inner + 4) += 1;
+        assert_eq!(*map.get(one), 1);
+
+        *concurrent.get_mut(one) += 1;
+        assert_eq!(*concurrent.get(one), 2);
+    }
+
+    #[cfg(feature = "stats")]
+    #[test]
+    fn test_entry_stats() {
+        let map = FnMap::new();
+
+        let hot = || 1;
+        let cold = || 2;
+
+        map.get(cold);
+        for _ in 0..3 {
+            map.get(hot);
+        }
+
+        assert_eq!(map.entry_stats(hot).unwrap().reads, 2);
+        assert_eq!(map.entry_stats(cold).unwrap().reads, 0);
+        assert_eq!(map.entry_stats(|| 3), None);
+    }
+
+    #[test]
+    fn test_recompute() {
+        use alloc::{vec, vec::Vec};
+
+        let mut map = FnMap::new();
+        let items = || vec![1];
+
+        assert_eq!(map.recompute(items, |old| old.unwrap_or_default()), &[]);
+
+        map.remove(items);
+        map.get(items);
+        let value = map.recompute(items, |old: Option<Vec<i32>>| {
+            let mut items = old.unwrap();
+            items.push(2);
+            items
+        });
+        assert_eq!(value, &[1, 2]);
+        assert_eq!(map.get(items), &[1, 2]);
+    }
+
+    #[test]
+    fn test_iter() {
+        use alloc::vec;
+        use core::sync::atomic::{AtomicUsize, Ordering};
+
+        static COMPUTED: AtomicUsize = AtomicUsize::new(0);
+
+        let map = FnMap::new();
+        let items = || {
+            COMPUTED.fetch_add(1, Ordering::Relaxed);
+            vec![1, 2, 3]
+        };
+
+        assert_eq!(map.get_iter(items).sum::<i32>(), 6);
+        assert_eq!(map.get_iter(items).copied().max(), Some(3));
+        assert_eq!(COMPUTED.load(Ordering::Relaxed), 1);
+    }
+
+    #[cfg(feature = "no-unsafe-marker")]
+    #[test]
+    fn test_no_unsafe_marker() {
+        let map = FnMap::new();
+        let concurrent = ConcurrentFnMap::new();
+
+        assert_eq!(*map.get(|| 1), 1);
+        assert_eq!(*concurrent.get(|| 2), 2);
+        assert_eq!(map.into_frozen_arc().get(|| 3), None);
+    }
+
+    #[test]
+    fn test_namespace() {
+        use core::sync::atomic::{AtomicUsize, Ordering};
+
+        static COMPUTED: AtomicUsize = AtomicUsize::new(0);
+
+        let mut map = FnMap::new();
+        let one = || {
+            COMPUTED.fetch_add(1, Ordering::Relaxed);
+            1
+        };
+
+        map.get_ns::<0, _>(one);
+        map.get_ns::<1, _>(one);
+        map.get(one);
+        assert_eq!(COMPUTED.load(Ordering::Relaxed), 3);
+
+        map.reset_namespace::<1>();
+
+        map.get_ns::<0, _>(one);
+        map.get(one);
+        assert_eq!(COMPUTED.load(Ordering::Relaxed), 3);
+
+        map.get_ns::<1, _>(one);
+        assert_eq!(COMPUTED.load(Ordering::Relaxed), 4);
+    }
+
+    #[test]
+    fn test_stable_ptr() {
+        use alloc::{boxed::Box, string::String};
+
+        struct Owner {
+            map: FnMap,
+        }
+
+        let map = FnMap::new();
+        let value = || String::from("stable");
+        let ptr = map.stable_ptr(value);
+
+        let owner = Box::new(Owner { map });
+        // SAFETY: value is not dropped while owner is alive
+        assert_eq!(unsafe { ptr.as_ref() }, "stable");
+        assert_eq!(owner.map.stable_ptr(value).as_ptr(), ptr.as_ptr());
+    }
+
+    #[test]
+    fn test_probe() {
+        use super::Probe;
+
+        fn one() -> i32 {
+            1
+        }
+
+        let map = FnMap::new();
+
+        match map.probe(one) {
+            Probe::Hit(_) => panic!("value is not computed yet"),
+            Probe::Miss(token) => assert_eq!(*token.compute(2), 2),
+        }
+
+        assert!(matches!(map.probe(one), Probe::Hit(2)));
+        assert_eq!(*map.get(one), 2);
+    }
+
+    #[test]
+    fn test_aligned() {
+        #[repr(align(64))]
+        struct Simd([u8; 64]);
+
+        let map = FnMap::new();
+
+        let value = map.get_aligned(|| 1_u8, 64);
+        assert_eq!(*value, 1);
+        assert_eq!((value as *const u8).align_offset(64), 0);
+
+        let simd = map.get_aligned(|| Simd([2; 64]), 128);
+        assert_eq!(simd.0, [2; 64]);
+        assert_eq!((simd as *const Simd).align_offset(128), 0);
+    }
+
+    #[test]
+    fn test_dump_sorted() {
+        use type_key::TypeKey;
+
+        fn one() -> i32 {
+            1
+        }
+        fn two() -> u8 {
+            2
+        }
+        fn three() -> u64 {
+            3
+        }
+
+        let forward = FnMap::new();
+        forward.get(one);
+        forward.get(two);
+        forward.get(three);
+
+        let backward = ConcurrentFnMap::new();
+        backward.get(three);
+        backward.get(two);
+        backward.get(one);
+
+        let keys = forward.dump_sorted();
+        assert!(keys.windows(2).all(|pair| pair[0] < pair[1]));
+        assert_eq!(keys, backward.dump_sorted());
+
+        let mut expected = [
+            TypeKey::of_val(&one),
+            TypeKey::of_val(&two),
+            TypeKey::of_val(&three),
+        ];
+        expected.sort();
+        assert_eq!(keys, expected);
+    }
+
+    #[test]
+    fn test_compute_budget() {
+        let mut map = FnMap::new().with_compute_budget(2);
+
+        assert_eq!(*map.get_budgeted(|| 1, || 0), 1);
+        assert_eq!(*map.get_budgeted(|| 2, || 0), 2);
+        assert_eq!(map.remaining_budget(), Some(0));
+
+        assert_eq!(*map.get_budgeted(|| 3, || 0), 0);
+        assert_eq!(*map.get_budgeted(|| 4, || 0), 0);
+        assert_eq!(map.dump_sorted().len(), 2);
+
+        map.reset();
+        assert_eq!(map.remaining_budget(), Some(2));
+        assert_eq!(*map.get_budgeted(|| 3, || 0), 3);
+
+        // hit is counted once
+        #[cfg(feature = "stats")]
+        {
+            let five = || 5;
+            map.get_budgeted(five, || 0);
+
+            let hits = map.stats().hits;
+            assert_eq!(*map.get_budgeted(five, || 0), 5);
+            assert_eq!(map.stats().hits, hits + 1);
+        }
+    }
+
+    #[test]
+    fn test_share() {
+        use alloc::sync::Arc;
+        use core::cell::Cell;
+
+        let computed = Cell::new(0);
+        let value = || {
+            computed.set(computed.get() + 1);
+            1
+        };
+
+        let mut map = FnMap::new();
+        assert_eq!(*map.get(value), 1);
+
+        let first = map.share(value);
+        let second = map.share(value);
+        assert!(Arc::ptr_eq(&first, &second));
+        assert_eq!(computed.get(), 1);
+
+        map.reset();
+        assert_eq!(*first, 1);
+    }
+
+    #[test]
+    fn test_keys_equal() {
+        use super::compare::EqRegistry;
+
+        fn one() -> i32 {
+            1
+        }
+
+        let value = |n| move || n;
+        let a = FnMap::new();
+        let b = FnMap::new();
+
+        a.get(one);
+        a.get(value(2));
+        b.get(value(3));
+        assert!(!a.keys_equal(&b));
+
+        b.get(one);
+        assert!(a.keys_equal(&b));
+
+        let mut registry = EqRegistry::new();
+        assert!(a.values_equal(&b, &registry));
+
+        registry.register::<i32>();
+        assert!(!a.values_equal(&b, &registry));
+    }
+
+    #[test]
+    fn test_values_equal_type_mismatch() {
+        use type_key::TypeKey;
+
+        use super::compare::EqRegistry;
+
+        let key = TypeKey::of::<()>();
+        let a = FnMap::new();
+        let b = FnMap::new();
+
+        // SAFETY: each map stores only one type using key
+        unsafe {
+            a.get_ptr_by_key(key, || 1_i32);
+            b.get_ptr_by_key(key, || 1_u8);
+        }
+        assert!(a.keys_equal(&b));
+
+        let mut registry = EqRegistry::new();
+        registry.register::<i32>();
+        registry.register::<u8>();
+        assert!(!a.values_equal(&b, &registry));
+        assert!(!b.values_equal(&a, &registry));
+    }
+
+    #[test]
+    fn test_keyed_hasher() {
+        use alloc::{format, string::String};
+        use core::{
+            cell::Cell,
+            hash::{BuildHasherDefault, Hasher},
+        };
+
+        // every keys collide
+        #[derive(Default)]
+        struct Colliding;
+
+        impl Hasher for Colliding {
+            fn finish(&self) -> u64 {
+                0
+            }
+
+            fn write(&mut self, _: &[u8]) {}
+        }
+
+        let map = FnMap::new();
+        let computed = Cell::new(0);
+        let parse = |key: &String| {
+            computed.set(computed.get() + 1);
+            key.parse::<usize>().unwrap()
+        };
+
+        for _ in 0..2 {
+            for i in 0..1000 {
+                let key = format!("{i:0>width$}", width = i % 7 + 1);
+
+                assert_eq!(
+                    *map.get_keyed_with_hasher::<BuildHasherDefault<Colliding>, _, _>(key, parse),
+                    i
+                );
+            }
+        }
+        assert_eq!(computed.get(), 1000);
+
+        assert_eq!(*map.get_keyed(String::from("7"), parse), 7);
+        assert_eq!(computed.get(), 1001);
+    }
+
+    #[test]
+    fn test_checked() {
+        use alloc::string::ToString;
+        use type_key::TypeKey;
+
+        use super::TypeMismatch;
+
+        fn one() -> i32 {
+            1
+        }
+
+        let map = FnMap::new();
+        let key = TypeKey::of_val(&one);
+        assert_eq!(map.get_checked::<i32>(key), Ok(None));
+
+        map.get(one);
+        assert_eq!(map.get_checked::<i32>(key), Ok(Some(&1)));
+
+        let mismatch = TypeMismatch {
+            expected: "u32",
+            found: "i32",
+        };
+        assert_eq!(map.get_checked::<u32>(key), Err(mismatch));
+        assert_eq!(
+            mismatch.to_string(),
+            "stored value is type of `i32`, not `u32`"
+        );
+    }
+
+    #[test]
+    fn test_auto_reset() {
+        use core::cell::Cell;
+
+        let computed = Cell::new(0);
+        let value = || {
+            computed.set(computed.get() + 1);
+            computed.get()
+        };
+
+        let map = FnMap::new().with_auto_reset_after(3);
+
+        for _ in 0..3 {
+            assert_eq!(*map.get(value), 1);
+        }
+        assert_eq!(map.accesses(), 3);
+
+        let first = map.get(value);
+        assert_eq!(*first, 2);
+        assert_eq!(map.accesses(), 1);
+        assert_eq!(computed.get(), 2);
+
+        for _ in 0..3 {
+            map.get(value);
+        }
+        assert_eq!(*map.get(value), 3);
+        // retired value is still valid
+        assert_eq!(*first, 2);
+    }
+
+    #[test]
+    fn test_auto_reset_frees() {
+        use core::sync::atomic::{AtomicUsize, Ordering};
+
+        static DROPPED: AtomicUsize = AtomicUsize::new(0);
+
+        struct Tracked(u64);
+
+        impl Drop for Tracked {
+            fn drop(&mut self) {
+                DROPPED.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+
+        let mut map = FnMap::new().with_auto_reset_after(2);
+
+        for _ in 0..5 {
+            assert_eq!(map.get(|| Tracked(1)).0, 1);
+        }
+        // two values are retired by shared gets and kept allocated
+        assert_eq!(DROPPED.load(Ordering::Relaxed), 0);
+        assert_eq!(map.allocated_bytes(), 24);
+
+        map.maintain();
+        assert_eq!(DROPPED.load(Ordering::Relaxed), 3);
+        assert_eq!(map.allocated_bytes(), 0);
+
+        for _ in 0..5 {
+            assert_eq!(map.get_mut(|| Tracked(1)).0, 1);
+        }
+        // dropped by exclusive gets
+        assert_eq!(DROPPED.load(Ordering::Relaxed), 5);
+
+        map.reset();
+        assert_eq!(DROPPED.load(Ordering::Relaxed), 6);
+    }
+
+    #[test]
+    fn test_scoped_value() {
+        fn scratch() -> i32 {
+            1
+        }
+
+        let mut map = FnMap::new();
+
+        {
+            let value = map.get_scoped(scratch);
+            assert_eq!(*value, 1);
+            assert_eq!(value.map.dump_sorted().len(), 1);
+        }
+
+        assert!(map.dump_sorted().is_empty());
+        assert!(!map.remove(scratch));
+    }
+
+    #[cfg(feature = "debug-keys")]
+    #[test]
+    fn test_on_miss() {
+        extern crate std;
+
+        use alloc::{sync::Arc, vec::Vec};
+        use core::any;
+        use std::sync::Mutex;
+        use type_key::TypeKey;
+
+        fn one() -> i32 {
+            1
+        }
+        fn two() -> i32 {
+            2
+        }
+
+        let misses = Arc::new(Mutex::new(Vec::new()));
+        let mut map = FnMap::new();
+        map.set_on_miss({
+            let misses = misses.clone();
+            move |name, key| misses.lock().unwrap().push((name, key))
+        });
+
+        map.get(two);
+        map.get(one);
+        map.get(two);
+
+        assert_eq!(
+            *misses.lock().unwrap(),
+            [
+                (any::type_name_of_val(&two), TypeKey::of_val(&two)),
+                (any::type_name_of_val(&one), TypeKey::of_val(&one)),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_field() {
+        use core::cell::Cell;
+
+        struct Config {
+            name: &'static str,
+            port: u16,
+        }
+
+        let parsed = Cell::new(0);
+        let config = || {
+            parsed.set(parsed.get() + 1);
+            Config {
+                name: "server",
+                port: 80,
+            }
+        };
+
+        let extracted = Cell::new(0);
+        let name = |config: &Config| {
+            extracted.set(extracted.get() + 1);
+            config.name
+        };
+        let port = |config: &Config| {
+            extracted.set(extracted.get() + 1);
+            config.port
+        };
+
+        let mut map = FnMap::new();
+        assert_eq!(*map.get_field(config, name), "server");
+        assert_eq!(*map.get_field(config, port), 80);
+        assert_eq!((parsed.get(), extracted.get()), (1, 2));
+
+        assert!(map.remove_field(config, name));
+        assert_eq!(*map.get_field(config, port), 80);
+        assert_eq!(*map.get_field(config, name), "server");
+        assert_eq!((parsed.get(), extracted.get()), (1, 3));
+    }
+
+    #[test]
+    fn test_recursive() {
+        use core::cell::Cell;
+
+        fn fib(map: &FnMap, computed: &Cell<usize>, n: u64) -> u64 {
+            *map.get_recursive_keyed(n, |map, &n| {
+                computed.set(computed.get() + 1);
+
+                match n {
+                    0 | 1 => n,
+                    _ => fib(map, computed, n - 1) + fib(map, computed, n - 2),
+                }
+            })
+        }
+
+        let map = FnMap::new();
+        let computed = Cell::new(0);
+
+        assert_eq!(fib(&map, &computed, 50), 12_586_269_025);
+        assert_eq!(computed.get(), 51);
+
+        assert_eq!(fib(&map, &computed, 30), 832_040);
+        assert_eq!(computed.get(), 51);
+
+        let double = |map: &FnMap| map.get_recursive_keyed(25_u64, |_, n| *n) * 2;
+        assert_eq!(*map.get_recursive(double), 50);
+    }
+
+    #[test]
+    #[should_panic(expected = "cycle detected")]
+    fn test_recursive_cycle() {
+        fn cyclic(map: &FnMap) -> i32 {
+            *map.get_recursive(cyclic) + 1
+        }
+
+        FnMap::new().get_recursive(cyclic);
+    }
+
+    #[test]
+    fn test_report() {
+        use alloc::string::String;
+
+        let map = FnMap::new().with_name("config");
+        map.get(|| 1_u64);
+        map.get(|| 2_u32);
+
+        let mut report = String::new();
+        map.report(&mut report).unwrap();
+
+        assert!(report.starts_with("map `config`\n"));
+        assert!(report.contains("entries: 2\n"));
+        assert!(report.contains("value bytes: 12\n"));
+        assert!(report.contains("bump bytes: "));
+    }
+
+    #[test]
+    fn test_handle() {
+        let mut map = FnMap::new();
+        let other = FnMap::new();
+
+        let one = || 1;
+        let handle = map.handle(one);
+        map.get(|| 2);
+        assert_eq!(handle.get(&map), Some(&1));
+        assert_eq!(handle.get(&other), None);
+
+        map.reset();
+        assert_eq!(handle.get(&map), None);
+
+        // recomputed value gets new handle
+        let handle = map.handle(one);
+        assert_eq!(handle.get(&map), Some(&1));
+        assert!(map.remove(one));
+        assert_eq!(handle.get(&map), None);
+    }
+
+    #[cfg(not(feature = "no-unsafe-marker"))]
+    #[test]
+    fn test_frozen_trait() {
+        use super::FrozenFnMap;
+
+        const fn is_send<T: Send>() {}
+        const fn is_sync<T: Sync>() {}
+
+        is_send::<FrozenFnMap>();
+        is_sync::<FrozenFnMap>();
+    }
+
+    #[cfg(all(feature = "lock-free-read", not(feature = "no-unsafe-marker")))]
+    #[test]
+    fn test_lock_free_read() {
+        extern crate std;
+        use std::thread;
+
+        let mut map = ConcurrentFnMap::new();
+        let one = || 1;
+        map.get(one);
+
+        // hits do not wait for writer holding the lock
+        let raw = map.raw.write();
+        thread::scope(|scope| {
+            assert_eq!(scope.spawn(|| *map.get(one)).join().unwrap(), 1);
+        });
+        drop(raw);
+
+        #[cfg(feature = "stats")]
+        assert_eq!(map.stats().hits, 1);
+
+        map.bump_epoch();
+        assert_eq!(*map.get(one), 1);
+        #[cfg(feature = "stats")]
+        assert_eq!(map.stats().misses, 2);
+
+        // removed and reset values are not read from snapshot
+        assert!(map.remove(one));
+        assert_eq!(*map.get(one), 1);
+        map.reset();
+        assert_eq!(*map.get(one), 1);
+        #[cfg(feature = "stats")]
+        assert_eq!(map.stats().misses, 4);
+    }
+
+    #[cfg(all(feature = "lock-free-read", not(feature = "no-unsafe-marker")))]
+    #[test]
+    fn test_lock_free_read_stress() {
+        extern crate std;
+        use core::sync::atomic::{AtomicBool, Ordering};
+        use std::{thread, vec::Vec};
+
+        fn value<const N: usize>() -> Vec<usize> {
+            [N; 8].to_vec()
+        }
+
+        macro_rules! check {
+            ($map:expr, $($n:literal)*) => {
+                $(assert_eq!(*$map.get(value::<$n>), [$n; 8]);)*
+            };
+        }
+
+        let mut map = ConcurrentFnMap::new();
+        for _ in 0..10 {
+            let stop = AtomicBool::new(false);
+
+            thread::scope(|scope| {
+                for _ in 0..4 {
+                    scope.spawn(|| {
+                        while !stop.load(Ordering::Relaxed) {
+                            check!(map, 0 1 2 3);
+                        }
+                    });
+                }
+
+                // replace snapshots being read by inserting and invalidating values
+                for _ in 0..1000 {
+                    check!(map, 0 1 2 3 4 5 6 7 8 9 10 11 12 13 14 15);
+                    map.bump_epoch();
+                }
+                stop.store(true, Ordering::Relaxed);
+            });
+
+            map.reset();
+        }
+    }
+
+    #[cfg(all(feature = "std", not(feature = "no-unsafe-marker")))]
+    #[test]
+    fn test_max_concurrent_computes_fresh() {
+        extern crate std;
+
+        use core::{
+            sync::atomic::{AtomicUsize, Ordering},
+            time::Duration,
+        };
+        use std::thread;
+
+        static RUNNING: AtomicUsize = AtomicUsize::new(0);
+        static MAX_RUNNING: AtomicUsize = AtomicUsize::new(0);
+
+        fn compute(n: u32) -> u32 {
+            let running = RUNNING.fetch_add(1, Ordering::SeqCst) + 1;
+            MAX_RUNNING.fetch_max(running, Ordering::SeqCst);
+            thread::sleep(Duration::from_millis(20));
+
+            RUNNING.fetch_sub(1, Ordering::SeqCst);
+            n
+        }
+
+        let map = ConcurrentFnMap::new().with_max_concurrent_computes(1);
+
+        thread::scope(|scope| {
+            let first = scope.spawn(|| {
+                let (value, fresh) = map.get_fresh(|| compute(1));
+                (*value, fresh)
+            });
+            let second = scope.spawn(|| {
+                let (value, fresh) = map.get_fresh(|| compute(2));
+                (*value, fresh)
+            });
+
+            assert_eq!(first.join().unwrap(), (1, true));
+            assert_eq!(second.join().unwrap(), (2, true));
+        });
+        assert_eq!(MAX_RUNNING.load(Ordering::SeqCst), 1);
+
+        // stored value is not reported as computed
+        let key = || compute(3);
+        assert_eq!(map.get_fresh(key), (&3, true));
+        assert_eq!(map.get_fresh(key), (&3, false));
+    }
+
+    #[cfg(all(feature = "std", not(feature = "no-unsafe-marker")))]
+    #[test]
+    fn test_once_timeout_manual_clock() {
+        extern crate std;
+
+        use crate::clock::{Clock, ManualClock};
+        use core::{
+            sync::atomic::{AtomicBool, Ordering},
+            time::Duration,
+        };
+        use std::thread;
+
+        static STARTED: AtomicBool = AtomicBool::new(false);
+        static RELEASED: AtomicBool = AtomicBool::new(false);
+
+        // releases computation even if assertion fails
+        struct Release;
+        impl Drop for Release {
+            fn drop(&mut self) {
+                RELEASED.store(true, Ordering::Release);
+            }
+        }
+
+        // tells when waiter has read its deadline
+        struct Observed<'a>(&'a ManualClock, AtomicBool);
+        impl Clock for Observed<'_> {
+            fn now(&self) -> u64 {
+                self.1.store(true, Ordering::Release);
+                self.0.now()
+            }
+        }
+
+        let map = ConcurrentFnMap::new();
+        let clock = ManualClock::new();
+        let observed = Observed(&clock, AtomicBool::new(false));
+        let slow = || {
+            STARTED.store(true, Ordering::Release);
+            while !RELEASED.load(Ordering::Acquire) {
+                thread::yield_now();
+            }
+            1
+        };
+
+        thread::scope(|scope| {
+            let release = Release;
+            let computing = scope.spawn(|| map.get_or_init_once_timeout(slow, 100, &clock));
+            while !STARTED.load(Ordering::Acquire) {
+                thread::yield_now();
+            }
+
+            let waiting = scope.spawn(|| map.get_or_init_once_timeout(slow, 5, &observed));
+            while !observed.1.load(Ordering::Acquire) {
+                thread::yield_now();
+            }
+
+            // waiter times out only when clock reaches the deadline, regardless of wall time
+            clock.advance(4);
+            thread::sleep(Duration::from_millis(20));
+            assert!(!waiting.is_finished());
+
+            clock.advance(1);
+            assert_eq!(waiting.join().unwrap(), None);
+            assert_eq!(clock.now(), 5);
+
+            drop(release);
+            assert_eq!(computing.join().unwrap(), Some(&1));
+        });
+    }
+
+    #[cfg(feature = "compress")]
+    #[test]
+    fn test_compressed() {
+        use alloc::vec::Vec;
+        use core::sync::atomic::{AtomicUsize, Ordering};
+
+        static COMPUTED: AtomicUsize = AtomicUsize::new(0);
+
+        fn blob() -> Vec<u8> {
+            COMPUTED.fetch_add(1, Ordering::Relaxed);
+
+            (0..1 << 20)
+                .map(|i: u32| (i % 251) as u8 ^ (i >> 12) as u8)
+                .collect()
+        }
+
+        let map = FnMap::new();
+        let raw = blob();
+        assert_eq!(map.get_compressed(blob), raw);
+        assert_eq!(map.get_compressed(blob), raw);
+        assert_eq!(COMPUTED.load(Ordering::Relaxed), 2);
+
+        // cached footprint is smaller than raw bytes
+        assert!(map.allocated_bytes() < raw.len() / 10);
+    }
 }
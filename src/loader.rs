@@ -0,0 +1,22 @@
+/// Reusable loader of values cached by [`FnMap::get_via`]
+///
+/// Value is cached using type of the loader and value, so each loader type has its own value.
+/// ```
+/// use fn_map::{loader::Loader, FnMap};
+///
+/// struct Config;
+///
+/// impl Loader<String> for Config {
+///     fn load(&self) -> String {
+///         String::from("config")
+///     }
+/// }
+///
+/// let map = FnMap::new();
+/// assert_eq!(map.get_via(&Config), "config");
+/// ```
+///
+/// [`FnMap::get_via`]: crate::FnMap::get_via
+pub trait Loader<T> {
+    fn load(&self) -> T;
+}
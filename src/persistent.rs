@@ -0,0 +1,389 @@
+use core::{
+    cell::UnsafeCell,
+    mem,
+    ptr::{self, NonNull},
+    slice, str,
+};
+use std::{
+    fs::{File, OpenOptions},
+    io,
+    os::fd::AsRawFd,
+    path::Path,
+};
+
+use alloc::{borrow::ToOwned, string::String};
+use hashbrown::HashMap;
+
+/// Plain old data which can be stored in [`PersistentFnMap`]
+///
+/// # Safety
+/// Type must be `#[repr(C)]` or primitive without padding, pointers and destructors,
+/// and every bit pattern must be valid value of the type.
+pub unsafe trait Pod: Copy + 'static {}
+
+macro_rules! impl_pod {
+    ($($ty:ty),*) => {
+        $(
+            // SAFETY: primitive numbers are valid for every bit patterns
+            unsafe impl Pod for $ty {}
+        )*
+    };
+}
+
+impl_pod!(u8, u16, u32, u64, u128, usize, i8, i16, i32, i64, i128, isize, f32, f64);
+
+// SAFETY: arrays of Pod have no padding between elements
+unsafe impl<T: Pod, const N: usize> Pod for [T; N] {}
+
+const MAGIC: [u8; 8] = *b"FNMAPv1\0";
+
+// magic and used bytes
+const HEADER: usize = 16;
+// name length, size and alignment of value
+const RECORD: usize = 24;
+
+// mapping is aligned to page
+const MAX_ALIGN: usize = 4096;
+
+#[derive(Debug)]
+struct Entry {
+    offset: usize,
+    size: usize,
+    align: usize,
+}
+
+#[derive(Debug)]
+/// Single thread only FnMap storing plain old data in memory mapped file.
+///
+/// Values are keyed by names instead of closures since closure types are not stable across builds,
+/// and they are loaded again when the file is reopened.
+/// Only [`Pod`] values are supported and a value cannot be removed once stored.
+///
+/// Size of the file is fixed on open, so values are never moved while the map is alive.
+pub struct PersistentFnMap {
+    ptr: NonNull<u8>,
+    len: usize,
+    _file: File,
+
+    // offset of values in the mapping
+    index: UnsafeCell<HashMap<String, Entry>>,
+}
+
+impl PersistentFnMap {
+    /// Open or create file at `path` holding at least `capacity` bytes
+    ///
+    /// # Safety
+    /// The file must not be modified by anyone else while the map is alive.
+    pub unsafe fn open(path: impl AsRef<Path>, capacity: usize) -> io::Result<Self> {
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(path)?;
+
+        let existing = file.metadata()?.len() as usize;
+        let len = existing.max(capacity).max(HEADER);
+        if len > existing {
+            file.set_len(len as u64)?;
+        }
+
+        // SAFETY: maps the whole file, which cannot be modified by others
+        let ptr = unsafe {
+            libc::mmap(
+                ptr::null_mut(),
+                len,
+                libc::PROT_READ | libc::PROT_WRITE,
+                libc::MAP_SHARED,
+                file.as_raw_fd(),
+                0,
+            )
+        };
+        if ptr == libc::MAP_FAILED {
+            return Err(io::Error::last_os_error());
+        }
+
+        let map = Self {
+            // SAFETY: successful mapping is not null
+            ptr: unsafe { NonNull::new_unchecked(ptr.cast()) },
+            len,
+            _file: file,
+            index: UnsafeCell::default(),
+        };
+
+        if map.bytes(0, MAGIC.len()) == [0; 8] {
+            // SAFETY: header is in bounds and not borrowed
+            unsafe { ptr::copy_nonoverlapping(MAGIC.as_ptr(), map.ptr.as_ptr(), MAGIC.len()) };
+        } else if map.bytes(0, MAGIC.len()) != MAGIC {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "invalid header"));
+        }
+
+        map.load()?;
+        Ok(map)
+    }
+
+    /// Size of the file
+    pub const fn capacity(&self) -> usize {
+        self.len
+    }
+
+    /// Bytes used by stored values and their records
+    pub fn used_bytes(&self) -> usize {
+        // checked on load and only grows in bounds while appending
+        self.used().expect("used bytes overflow")
+    }
+
+    /// used bytes read from the header, `None` if it overflows
+    fn used(&self) -> Option<usize> {
+        HEADER.checked_add(self.read_word(MAGIC.len()))
+    }
+
+    /// Get or compute value using name
+    ///
+    /// # Panics
+    /// Panics if value of `name` is stored with different size or alignment,
+    /// or the file does not have enough space for the value.
+    pub fn get<T: Pod>(&self, name: &str, init: impl FnOnce() -> T) -> &T {
+        if let Some(value) = self.lookup(name) {
+            return value;
+        }
+
+        // accuire value first before borrowing exclusively
+        let value = init();
+
+        // value may be stored while computing
+        if let Some(value) = self.lookup(name) {
+            return value;
+        }
+
+        let offset = self.append(name, &value);
+
+        // SAFETY: value is written at offset and never moved or written again
+        unsafe { &*self.ptr.as_ptr().add(offset).cast::<T>() }
+    }
+
+    fn lookup<T: Pod>(&self, name: &str) -> Option<&T> {
+        // SAFETY: index is only borrowed exclusively while appending
+        let entry = unsafe { &*self.index.get().cast_const() }.get(name)?;
+
+        assert!(
+            entry.size == mem::size_of::<T>() && entry.align == mem::align_of::<T>(),
+            "value `{name}` is stored with different layout"
+        );
+
+        // SAFETY: entry is in bounds and aligned for T, and every bit patterns are valid for Pod
+        Some(unsafe { &*self.ptr.as_ptr().add(entry.offset).cast::<T>() })
+    }
+
+    /// write record of `value` after stored values and return offset of the value
+    fn append<T: Pod>(&self, name: &str, value: &T) -> usize {
+        let (size, align) = (mem::size_of::<T>(), mem::align_of::<T>());
+        assert!(align <= MAX_ALIGN, "alignment of the value is too large");
+
+        let record = self.used_bytes();
+        let end = record_end(record, name.len(), size, align);
+        let Some((offset, end)) = end.filter(|&(_, end)| end <= self.len) else {
+            panic!("persistent map is full");
+        };
+
+        self.write_word(record, name.len());
+        self.write_word(record + 8, size);
+        self.write_word(record + 16, align);
+
+        // SAFETY: record is in bounds and past every borrowed values
+        unsafe {
+            let base = self.ptr.as_ptr();
+
+            ptr::copy_nonoverlapping(name.as_ptr(), base.add(record + RECORD), name.len());
+            base.add(offset).cast::<T>().write(*value);
+        }
+
+        // publish the record after it is written
+        self.write_word(MAGIC.len(), end - HEADER);
+
+        // SAFETY: index is not borrowed while appending
+        unsafe { &mut *self.index.get() }.insert(
+            name.to_owned(),
+            Entry {
+                offset,
+                size,
+                align,
+            },
+        );
+
+        offset
+    }
+
+    /// index records written in the file
+    fn load(&self) -> io::Result<()> {
+        let invalid = || io::Error::new(io::ErrorKind::InvalidData, "invalid record");
+
+        let used = self.used().filter(|&used| used <= self.len);
+        let Some(used) = used else {
+            return Err(invalid());
+        };
+
+        // SAFETY: index is not borrowed while loading
+        let index = unsafe { &mut *self.index.get() };
+
+        let mut record = HEADER;
+        while record < used {
+            if used - record < RECORD {
+                return Err(invalid());
+            }
+
+            let name_len = self.read_word(record);
+            let size = self.read_word(record + 8);
+            let align = self.read_word(record + 16);
+            if !align.is_power_of_two() || align > MAX_ALIGN {
+                return Err(invalid());
+            }
+
+            let end = record_end(record, name_len, size, align);
+            let Some((offset, end)) = end.filter(|&(_, end)| end <= used) else {
+                return Err(invalid());
+            };
+
+            let name =
+                str::from_utf8(self.bytes(record + RECORD, name_len)).map_err(|_| invalid())?;
+            index.insert(
+                name.to_owned(),
+                Entry {
+                    offset,
+                    size,
+                    align,
+                },
+            );
+
+            record = end;
+        }
+
+        Ok(())
+    }
+
+    fn bytes(&self, offset: usize, len: usize) -> &[u8] {
+        assert!(offset.checked_add(len).is_some_and(|end| end <= self.len));
+
+        // SAFETY: range is in bounds of the mapping
+        unsafe { slice::from_raw_parts(self.ptr.as_ptr().add(offset), len) }
+    }
+
+    fn read_word(&self, offset: usize) -> usize {
+        assert!(offset.checked_add(8).is_some_and(|end| end <= self.len));
+
+        // SAFETY: offset is in bounds and aligned to 8
+        unsafe { self.ptr.as_ptr().add(offset).cast::<u64>().read() as usize }
+    }
+
+    fn write_word(&self, offset: usize, word: usize) {
+        assert!(offset.checked_add(8).is_some_and(|end| end <= self.len));
+
+        // SAFETY: offset is in bounds, aligned to 8 and not borrowed
+        unsafe {
+            self.ptr
+                .as_ptr()
+                .add(offset)
+                .cast::<u64>()
+                .write(word as u64)
+        }
+    }
+}
+
+impl Drop for PersistentFnMap {
+    fn drop(&mut self) {
+        // SAFETY: mapping is created on open and not borrowed anymore
+        unsafe {
+            libc::msync(self.ptr.as_ptr().cast(), self.len, libc::MS_SYNC);
+            libc::munmap(self.ptr.as_ptr().cast(), self.len);
+        }
+    }
+}
+
+/// offset of the value and end of record starting at `record`, `None` if it overflows
+fn record_end(record: usize, name_len: usize, size: usize, align: usize) -> Option<(usize, usize)> {
+    let offset = align_up(record.checked_add(RECORD)?.checked_add(name_len)?, align)?;
+    let end = align_up(offset.checked_add(size)?, 8)?;
+
+    Some((offset, end))
+}
+
+const fn align_up(offset: usize, align: usize) -> Option<usize> {
+    match offset.checked_add(align - 1) {
+        Some(offset) => Some(offset & !(align - 1)),
+        None => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::{format, vec::Vec};
+    use std::{env, fs, io, process};
+
+    use super::{PersistentFnMap, MAGIC};
+
+    #[test]
+    fn test_reopen() {
+        let path = env::temp_dir().join(format!("fn-map-persistent-{}", process::id()));
+        let _ = fs::remove_file(&path);
+
+        {
+            // SAFETY: file is only used by this test
+            let map = unsafe { PersistentFnMap::open(&path, 4096) }.unwrap();
+
+            assert_eq!(*map.get("answer", || 42_u32), 42);
+            assert_eq!(*map.get("table", || [1.5_f64; 4]), [1.5; 4]);
+            assert_eq!(*map.get("answer", || 0_u32), 42);
+        }
+
+        {
+            // SAFETY: file is only used by this test
+            let map = unsafe { PersistentFnMap::open(&path, 4096) }.unwrap();
+
+            assert_eq!(*map.get("answer", || -> u32 { unreachable!() }), 42);
+            assert_eq!(
+                *map.get("table", || -> [f64; 4] { unreachable!() }),
+                [1.5; 4]
+            );
+            assert_eq!(*map.get("byte", || 7_u8), 7);
+        }
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_corrupted() {
+        let path = env::temp_dir().join(format!("fn-map-corrupted-{}", process::id()));
+
+        let open = |words: &[usize]| {
+            let mut file = Vec::from(MAGIC);
+            for word in words {
+                file.extend_from_slice(&(*word as u64).to_ne_bytes());
+            }
+            fs::write(&path, file).unwrap();
+
+            // SAFETY: file is only used by this test
+            unsafe { PersistentFnMap::open(&path, 4096) }
+                .map(|_| ())
+                .map_err(|err| err.kind())
+        };
+
+        assert_eq!(open(&[0]), Ok(()));
+
+        // used bytes overflowing with header
+        assert_eq!(open(&[usize::MAX - 8]), Err(io::ErrorKind::InvalidData));
+
+        // name length overflowing
+        assert_eq!(
+            open(&[24, usize::MAX - 8, 0, 1]),
+            Err(io::ErrorKind::InvalidData)
+        );
+
+        // value size overflowing
+        assert_eq!(
+            open(&[24, 0, usize::MAX - 16, 8]),
+            Err(io::ErrorKind::InvalidData)
+        );
+
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -1,81 +1,1158 @@
-use core::{mem::ManuallyDrop, ptr, ptr::NonNull};
+use core::{
+    alloc::Layout,
+    any, fmt,
+    hash::{BuildHasher, Hash, Hasher},
+    mem::{self, ManuallyDrop, MaybeUninit},
+    ptr,
+    ptr::NonNull,
+    sync::atomic::{AtomicU64, Ordering},
+};
 
+use alloc::vec::Vec;
 use bumpalo::Bump;
 use hashbrown::HashMap;
-use nohash_hasher::BuildNoHashHasher;
 use type_key::TypeKey;
 
+#[cfg(feature = "otel")]
+use crate::stats::Metric;
+#[cfg(feature = "stats")]
+use crate::stats::{Counters, EntryStats, Stats};
+
+/// Hasher of keys used by default
+///
+/// [`TypeId`] only hashes lower 64 bits, so it is used as hash directly.
+/// With `wide-key` feature every bits of [`TypeId`] are hashed instead.
+///
+/// [`TypeId`]: core::any::TypeId
+#[cfg(not(feature = "wide-key"))]
+pub type KeyHasher = nohash_hasher::BuildNoHashHasher<u64>;
+
+/// Hasher of keys used by default
+///
+/// [`TypeId`] only hashes lower 64 bits, so it is used as hash directly.
+/// With `wide-key` feature every bits of [`TypeId`] are hashed instead.
+///
+/// [`TypeId`]: core::any::TypeId
+#[cfg(feature = "wide-key")]
+pub type KeyHasher = core::hash::BuildHasherDefault<WideHasher>;
+
+#[derive(Debug, Default, Clone, Copy)]
+/// Hasher mixing every words written
+///
+/// Unlike [`BuildNoHashHasher`], it does not rely on keys hashing only one word.
+/// Hash of [`TypeKey`] itself only writes lower 64 bits,
+/// so [`RawFnMap`] writes every bits of its [`TypeKey`] keys with `wide-key` feature.
+/// Keys are always compared fully using [`Eq`] regardless of hasher.
+///
+/// [`BuildNoHashHasher`]: nohash_hasher::BuildNoHashHasher
+pub struct WideHasher(u64);
+
+impl Hasher for WideHasher {
+    fn finish(&self) -> u64 {
+        self.0
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for chunk in bytes.chunks(8) {
+            let mut word = [0; 8];
+            word[..chunk.len()].copy_from_slice(chunk);
+
+            self.write_u64(u64::from_ne_bytes(word));
+        }
+    }
+
+    fn write_u64(&mut self, word: u64) {
+        self.0 = (self.0.rotate_left(5) ^ word).wrapping_mul(0x517c_c1b7_2722_0a95);
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(transparent)]
+/// key of the inner map
+///
+/// With `wide-key` feature, [`TypeKey`] and [`FnPointer`] keys are hashed using all 128 bits of [`TypeId`].
+///
+/// [`FnPointer`]: crate::key::FnPointer
+/// [`TypeId`]: core::any::TypeId
+struct MapKey<K>(K);
+
+impl<K: Hash> Hash for MapKey<K> {
+    #[inline]
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        #[cfg(feature = "wide-key")]
+        if TypeKey::of::<K>() == TypeKey::of::<TypeKey>() {
+            // SAFETY: K is TypeKey
+            let key = unsafe { mem::transmute_copy::<K, TypeKey>(&self.0) };
+            state.write_u128(wide_bits(key));
+            return;
+        } else if TypeKey::of::<K>() == TypeKey::of::<(TypeKey, usize)>() {
+            // SAFETY: K is (TypeKey, usize)
+            let (key, address) = unsafe { mem::transmute_copy::<K, (TypeKey, usize)>(&self.0) };
+            state.write_u128(wide_bits(key));
+            state.write_usize(address);
+            return;
+        }
+
+        self.0.hash(state);
+    }
+}
+
+/// every bits of [`TypeId`] in the key
+///
+/// [`TypeId`]: core::any::TypeId
+#[cfg(feature = "wide-key")]
+#[inline]
+fn wide_bits(key: TypeKey) -> u128 {
+    // SAFETY: TypeKey is transparent TypeId, which is 128 bits without padding
+    unsafe { mem::transmute::<TypeKey, u128>(key) }
+}
+
+static GENERATION: AtomicU64 = AtomicU64::new(0);
+
+fn next_generation() -> u64 {
+    GENERATION.fetch_add(1, Ordering::Relaxed)
+}
+
 #[derive(Debug)]
 /// raw FnMap
-pub struct RawFnMap {
-    // [`TypeId`] only hashes lower 64 bits
-    map: HashMap<TypeKey, Val, BuildNoHashHasher<u64>>,
+pub struct RawFnMap<S = KeyHasher, K = TypeKey> {
+    map: HashMap<MapKey<K>, Val, S>,
+    // replaced values kept alive until reset
+    retired: Vec<Val>,
+
+    #[cfg(feature = "slab")]
+    slab: Slab,
+
+    // (dependent, dependency) pairs
+    #[cfg(feature = "deps")]
+    deps: Vec<(TypeKey, TypeKey)>,
 
     bump: ManuallyDrop<Bump>,
+    allocated: usize,
+    // largest size of bump chunks before last reset
+    peak_bump: usize,
+    generation: u64,
+    // any stored value needs to be dropped
+    needs_drop: bool,
+
+    name: Option<&'static str>,
+
+    #[cfg(feature = "stats")]
+    counters: Counters,
+
+    #[cfg(debug_assertions)]
+    collisions: usize,
 }
 
 impl RawFnMap {
     pub fn new() -> Self {
+        Self::with_hasher(KeyHasher::default())
+    }
+}
+
+impl<S: BuildHasher, K: Eq + Hash + Copy> RawFnMap<S, K> {
+    pub fn with_hasher(hasher: S) -> Self {
         Self {
-            map: HashMap::default(),
+            map: HashMap::with_hasher(hasher),
+            retired: Vec::new(),
+
+            #[cfg(feature = "slab")]
+            slab: Slab::default(),
+
+            #[cfg(feature = "deps")]
+            deps: Vec::new(),
 
             bump: ManuallyDrop::new(Bump::new()),
+            allocated: 0,
+            peak_bump: 0,
+            generation: next_generation(),
+            needs_drop: false,
+
+            name: None,
+
+            #[cfg(feature = "stats")]
+            counters: Counters::default(),
+
+            #[cfg(debug_assertions)]
+            collisions: 0,
         }
     }
 
-    pub fn get<T: 'static>(&self, key: &TypeKey) -> Option<NonNull<T>> {
-        Some(self.map.get(key)?.inner().cast::<T>())
+    /// Generation of stored values
+    ///
+    /// Changes every time a stored value is dropped.
+    /// Generation is unique across every maps so it identifies set of valid values of the map.
+    pub const fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    /// Label used to identify this map
+    pub const fn name(&self) -> Option<&'static str> {
+        self.name
+    }
+
+    pub fn set_name(&mut self, name: &'static str) {
+        self.name = Some(name);
+    }
+
+    pub fn get<T>(&self, key: &K) -> Option<NonNull<T>> {
+        let val = self.map.get(&MapKey(*key))?;
+
+        #[cfg(feature = "stats")]
+        {
+            self.counters.add_hits(1);
+            val.read(1, self.generation);
+        }
+
+        Some(val.inner().cast::<T>())
     }
 
     /// insert value
     ///
     /// Returned pointer cannot outlive Self
-    pub fn insert<T: 'static>(&mut self, key: TypeKey, value: T) -> NonNull<T> {
-        let value = Val(NonNull::from(self.bump.alloc(value)) as NonNull<dyn Erased>);
-        let ptr = value.inner();
+    pub fn insert<T: 'static>(&mut self, key: K, value: T) -> NonNull<T> {
+        let ptr = self.alloc_uninit::<T>().cast::<T>();
 
-        self.map.insert(key, value);
+        // SAFETY: pointer is allocated for the value
+        unsafe { ptr.as_ptr().write(value) };
 
-        ptr.cast::<T>()
+        // SAFETY: pointer is allocated from bump and initialized
+        unsafe { self.insert_ptr(key, ptr) }
     }
 
-    pub fn reset(&mut self) {
+    /// insert value at address aligned to `align`
+    ///
+    /// Returned pointer cannot outlive Self
+    ///
+    /// # Panics
+    /// Panics if `align` is not power of two or is smaller than alignment of `T`
+    pub fn insert_aligned<T: 'static>(&mut self, key: K, value: T, align: usize) -> NonNull<T> {
+        assert!(
+            align >= mem::align_of::<T>(),
+            "alignment is smaller than alignment of the value"
+        );
+        let layout =
+            Layout::from_size_align(mem::size_of::<T>(), align).expect("invalid alignment");
+
+        let ptr = self.alloc_layout(layout).cast::<T>();
+        // SAFETY: pointer is allocated for the value
+        unsafe { ptr.as_ptr().write(value) };
+
+        self.needs_drop |= mem::needs_drop::<T>();
+        self.insert_val(key, Val::sized(ptr, layout));
+
+        ptr
+    }
+
+    /// allocate space for a value without initializing it
+    ///
+    /// Returned pointer cannot outlive Self
+    pub fn alloc_uninit<T>(&mut self) -> NonNull<MaybeUninit<T>> {
+        self.alloc_layout(Layout::new::<T>()).cast()
+    }
+
+    /// discard space from [`RawFnMap::alloc_uninit`] without storing a value
+    ///
+    /// # Safety
+    /// `ptr` must be allocated from this map and not be stored
+    pub unsafe fn dealloc_uninit<T>(&mut self, ptr: NonNull<MaybeUninit<T>>) {
+        #[cfg(feature = "slab")]
+        self.slab.free(Layout::new::<T>(), ptr.cast());
+        #[cfg(not(feature = "slab"))]
+        let _ = ptr;
+    }
+
+    fn alloc_layout(&mut self, layout: Layout) -> NonNull<u8> {
+        #[cfg(feature = "slab")]
+        if let Some(ptr) = self.slab.take(&layout) {
+            return ptr;
+        }
+
+        self.allocated += layout.size();
+        self.bump.alloc_layout(layout)
+    }
+
+    /// insert value initialized in space from [`RawFnMap::alloc_uninit`]
+    ///
+    /// Returned pointer cannot outlive Self
+    ///
+    /// # Safety
+    /// `ptr` must be allocated from this map and point to initialized value.
+    ///
+    /// If `T` is not `'static`, Self must be reset or dropped before lifetime of `T` ends.
+    pub unsafe fn insert_ptr<T>(&mut self, key: K, ptr: NonNull<T>) -> NonNull<T> {
+        self.needs_drop |= mem::needs_drop::<T>();
+        self.insert_val(key, Val::sized(ptr, Layout::new::<T>()));
+
+        ptr
+    }
+
+    /// allocate `layout`, initialize it using `init` and insert it
+    ///
+    /// Used for values which cannot be expressed as sized type, like structs with trailing slice.
+    /// Returned pointer cannot outlive Self
+    ///
+    /// # Safety
+    /// `init` must initialize the space so `drop_fn` can drop it.
+    /// Every value stored using `key` must be read with layout initialized by `init`.
+    pub unsafe fn insert_dst(
+        &mut self,
+        key: K,
+        layout: Layout,
+        init: impl FnOnce(*mut u8),
+        drop_fn: unsafe fn(*mut u8),
+    ) -> NonNull<()> {
+        let ptr = self.alloc_layout(layout);
+        init(ptr.as_ptr());
+
+        self.needs_drop = true;
+        self.insert_val(key, Val::new(ptr, None, layout, drop_fn));
+
+        ptr.cast()
+    }
+
+    fn insert_val(&mut self, key: K, val: Val) {
+        #[cfg(debug_assertions)]
+        self.check_collision(&key);
+
+        #[cfg(feature = "stats")]
+        self.counters.miss();
+
+        if let Some(replaced) = self.map.insert(MapKey(key), val) {
+            #[cfg(feature = "slab")]
+            self.slab.release(replaced);
+            #[cfg(not(feature = "slab"))]
+            drop(replaced);
+            self.generation = next_generation();
+        }
+    }
+
+    /// remove and drop value stored using key
+    pub fn remove(&mut self, key: &K) -> bool {
+        let Some(val) = self.map.remove(&MapKey(*key)) else {
+            return false;
+        };
+
+        #[cfg(feature = "slab")]
+        self.slab.release(val);
+        #[cfg(not(feature = "slab"))]
+        drop(val);
+        self.generation = next_generation();
+
+        true
+    }
+
+    /// remove value stored using key and move it out
+    ///
+    /// # Safety
+    /// Value stored using key must be type of `T` and must not be pinned
+    pub unsafe fn take<T>(&mut self, key: &K) -> Option<T> {
+        let val = ManuallyDrop::new(self.map.remove(&MapKey(*key))?);
+
+        // SAFETY: value is initialized and never dropped by Val
+        let value = unsafe { val.inner().cast::<T>().as_ptr().read() };
+
+        #[cfg(feature = "slab")]
+        self.slab.free(val.layout(), val.inner().cast());
+        self.generation = next_generation();
+
+        Some(value)
+    }
+
+    /// remove and drop every values of type `ty`
+    ///
+    /// Returns number of removed values
+    pub fn remove_type(&mut self, ty: &TypeKey) -> usize {
+        let keys = self
+            .map
+            .iter()
+            .filter(|(_, val)| val.ty.as_ref() == Some(ty))
+            .map(|(key, _)| key.0)
+            .collect::<Vec<_>>();
+
+        for key in &keys {
+            self.remove(key);
+        }
+
+        keys.len()
+    }
+
+    /// remove and drop every values in namespace `ns`
+    ///
+    /// Returns number of removed values
+    pub fn remove_namespace(&mut self, ns: u64) -> usize {
+        let keys = self
+            .map
+            .iter()
+            .filter(|(_, val)| val.namespace == Some(ns))
+            .map(|(key, _)| key.0)
+            .collect::<Vec<_>>();
+
+        for key in &keys {
+            self.remove(key);
+        }
+
+        keys.len()
+    }
+
+    /// remove value stored using key without dropping it
+    ///
+    /// Retired value stays valid until reset.
+    pub fn retire(&mut self, key: &K) {
+        if let Some(val) = self.map.remove(&MapKey(*key)) {
+            self.retired.push(val);
+        }
+    }
+
+    /// remove every values without dropping them
+    ///
+    /// Retired values stay valid until reset.
+    pub fn retire_all(&mut self) {
+        self.retired.extend(self.map.drain().map(|(_, val)| val));
+        self.generation = next_generation();
+    }
+
+    /// store value without key, keeping it alive until reset
+    ///
+    /// Returned pointer cannot outlive Self
+    pub fn insert_retired<T: 'static>(&mut self, value: T) -> NonNull<T> {
+        let ptr = self.alloc_uninit::<T>().cast::<T>();
+
+        // SAFETY: pointer is allocated for the value
+        unsafe { ptr.as_ptr().write(value) };
+
+        self.needs_drop |= mem::needs_drop::<T>();
+        self.retired.push(Val::sized(ptr, Layout::new::<T>()));
+
+        ptr
+    }
+
+    /// epoch in which value stored using key is computed
+    pub fn epoch(&self, key: &K) -> Option<u64> {
+        Some(self.map.get(&MapKey(*key))?.epoch)
+    }
+
+    pub fn set_epoch(&mut self, key: &K, epoch: u64) {
+        if let Some(val) = self.map.get_mut(&MapKey(*key)) {
+            val.epoch = epoch;
+        }
+    }
+
+    /// record value of `dependent` is computed using value of `dependency`
+    #[cfg(feature = "deps")]
+    pub fn add_dependency(&mut self, dependent: TypeKey, dependency: TypeKey) {
+        if !self.deps.contains(&(dependent, dependency)) {
+            self.deps.push((dependent, dependency));
+        }
+    }
+
+    /// recorded (dependent, dependency) pairs
+    #[cfg(feature = "deps")]
+    pub fn dependencies(&self) -> &[(TypeKey, TypeKey)] {
+        &self.deps
+    }
+
+    pub fn set_namespace(&mut self, key: &K, ns: u64) {
+        if let Some(val) = self.map.get_mut(&MapKey(*key)) {
+            val.namespace = Some(ns);
+        }
+    }
+
+    /// version of value stored using key
+    pub fn version(&self, key: &K) -> Option<u64> {
+        Some(self.map.get(&MapKey(*key))?.version)
+    }
+
+    pub fn set_version(&mut self, key: &K, version: u64) {
+        if let Some(val) = self.map.get_mut(&MapKey(*key)) {
+            val.version = version;
+        }
+    }
+
+    /// Number of stored values
+    pub fn len(&self) -> usize {
+        self.map.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.map.is_empty()
+    }
+
+    /// pointer and type of value stored using key
+    ///
+    /// Type is `None` for values stored by [`RawFnMap::insert_dst`].
+    pub fn entry(&self, key: &K) -> Option<(NonNull<()>, Option<TypeKey>)> {
+        let val = self.map.get(&MapKey(*key))?;
+
+        Some((val.inner(), val.ty))
+    }
+
+    /// keys of stored values in arbitrary order
+    pub fn keys(&self) -> impl Iterator<Item = K> + '_ {
+        self.map.keys().map(|key| key.0)
+    }
+
+    /// name of type of value stored using key
+    pub fn type_name(&self, key: &K) -> Option<&'static str> {
+        Some(self.map.get(&MapKey(*key))?.type_name)
+    }
+
+    /// every keys are stored in both maps
+    pub fn keys_equal<S2: BuildHasher>(&self, other: &RawFnMap<S2, K>) -> bool {
+        self.len() == other.len() && self.map.keys().all(|key| other.map.contains_key(key))
+    }
+
+    /// number of accesses to value stored using key
+    pub fn accesses(&self, key: &K) -> Option<u64> {
+        Some(self.map.get(&MapKey(*key))?.accesses)
+    }
+
+    /// count access to value stored using key at `tick`
+    pub fn touch(&mut self, key: &K, tick: u64) {
+        if let Some(val) = self.map.get_mut(&MapKey(*key)) {
+            val.accesses += 1;
+            val.last_access = tick;
+        }
+    }
+
+    pub fn set_last_access(&mut self, key: &K, tick: u64) {
+        if let Some(val) = self.map.get_mut(&MapKey(*key)) {
+            val.last_access = tick;
+        }
+    }
+
+    /// key of value with smallest rank computed from (accesses, last access tick)
+    pub fn least_used_by<R: Ord>(&self, mut rank: impl FnMut(u64, u64) -> R) -> Option<K> {
+        self.map
+            .iter()
+            .min_by_key(|(_, val)| rank(val.accesses, val.last_access))
+            .map(|(key, _)| key.0)
+    }
+
+    /// label value stored using key
+    #[cfg(feature = "debug-keys")]
+    pub fn set_label(&mut self, key: &K, label: &'static str) {
+        if let Some(val) = self.map.get_mut(&MapKey(*key)) {
+            val.label = label;
+        }
+    }
+
+    /// iterate labels and keys of stored values
+    ///
+    /// Value without label has empty label
+    #[cfg(feature = "debug-keys")]
+    pub fn dump(&self, mut f: impl FnMut(&'static str, K)) {
+        for (key, val) in &self.map {
+            f(val.label, key.0);
+        }
+    }
+
+    /// Keys of stored values in ascending order
+    ///
+    /// Unlike iteration order of the map, the order is reproducible for same set of keys.
+    pub fn dump_sorted(&self) -> Vec<K>
+    where
+        K: Ord,
+    {
+        let mut keys: Vec<K> = self.keys().collect();
+        keys.sort_unstable();
+        keys
+    }
+
+    /// Number of inserted keys sharing hash with a different key
+    ///
+    /// Colliding keys are still distinguished by [`Eq`], but they hint truncated [`TypeId`] hash collision.
+    ///
+    /// [`TypeId`]: core::any::TypeId
+    #[cfg(debug_assertions)]
+    pub const fn hash_collisions(&self) -> usize {
+        self.collisions
+    }
+
+    #[cfg(debug_assertions)]
+    fn check_collision(&mut self, key: &K) {
+        let hasher = self.map.hasher();
+        let hash = hasher.hash_one(MapKey(*key));
+
+        if self
+            .map
+            .raw_entry()
+            .from_hash(hash, |other| {
+                other.0 != *key && hasher.hash_one(other) == hash
+            })
+            .is_some()
+        {
+            self.collisions += 1;
+        }
+    }
+
+    /// Bytes of bump memory used by values since last reset
+    ///
+    /// Includes dead space of values dropped by [`RawFnMap::clear`]
+    pub const fn allocated_bytes(&self) -> usize {
+        self.allocated
+    }
+
+    /// Bytes of chunks allocated by bump
+    pub fn bump_bytes(&self) -> usize {
+        self.bump.allocated_bytes()
+    }
+
+    /// Largest bytes of chunks allocated by bump since created
+    pub fn peak_bump_bytes(&self) -> usize {
+        self.peak_bump.max(self.bump_bytes())
+    }
+
+    /// write human readable summary of the map
+    pub fn report(&self, w: &mut impl fmt::Write) -> fmt::Result
+    where
+        K: fmt::Debug,
+    {
+        match self.name {
+            Some(name) => writeln!(w, "map `{name}`")?,
+            None => writeln!(w, "map")?,
+        }
+
+        writeln!(w, "  entries: {}", self.len())?;
+        writeln!(w, "  value bytes: {}", self.allocated_bytes())?;
+        writeln!(
+            w,
+            "  bump bytes: {} (peak {})",
+            self.bump_bytes(),
+            self.peak_bump_bytes()
+        )?;
+
+        #[cfg(feature = "stats")]
+        {
+            let stats = self.stats();
+            writeln!(w, "  hits: {}, misses: {}", stats.hits, stats.misses)?;
+        }
+
+        #[cfg(feature = "debug-keys")]
+        {
+            writeln!(w, "  labeled entries:")?;
+            for (key, val) in &self.map {
+                if !val.label.is_empty() {
+                    writeln!(w, "    {}: {:?}", val.label, key.0)?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Bytes which can be allocated from current chunk of bump
+    ///
+    /// Allocating more than this allocates a new chunk.
+    pub fn remaining_chunk_capacity(&self) -> usize {
+        self.bump.chunk_capacity()
+    }
+
+    /// hit and miss counts
+    ///
+    /// Counted lookups found stored value as hits and inserted values as misses.
+    #[cfg(feature = "stats")]
+    pub fn stats(&self) -> Stats {
+        self.counters.get()
+    }
+
+    /// statistics of the map as metrics labeled with its name
+    #[cfg(feature = "otel")]
+    pub fn export_metrics(&self) -> Vec<Metric> {
+        Metric::export(self.name, self.stats(), self.len(), self.allocated_bytes())
+    }
+
+    /// count value dropped to make room for new one
+    #[cfg(feature = "stats")]
+    pub fn count_eviction(&self) {
+        self.counters.evict();
+    }
+
+    /// count hits of values found without accessing the map
+    #[cfg(feature = "stats")]
+    pub fn count_hits(&self, hits: u64) {
+        self.counters.add_hits(hits);
+    }
+
+    /// count hits of value stored using key found without lookup, last one made in `generation`
+    #[cfg(feature = "stats")]
+    pub fn add_hits(&self, key: &K, hits: u64, generation: u64) {
+        self.counters.add_hits(hits);
+
+        if let Some(val) = self.map.get(&MapKey(*key)) {
+            val.read(hits, generation);
+        }
+    }
+
+    /// read statistics of value stored using key
+    #[cfg(feature = "stats")]
+    pub fn entry_stats(&self, key: &K) -> Option<EntryStats> {
+        Some(self.map.get(&MapKey(*key))?.entry_stats())
+    }
+
+    /// add time spent computing value of type `ty`
+    #[cfg(all(feature = "stats", feature = "std"))]
+    pub fn add_compute_time(&mut self, ty: core::any::TypeId, elapsed: core::time::Duration) {
+        self.counters.add_compute_time(ty, elapsed);
+    }
+
+    /// cumulative computation time of each value type
+    #[cfg(all(feature = "stats", feature = "std"))]
+    pub fn compute_time(&self) -> &[(core::any::TypeId, core::time::Duration)] {
+        self.counters.compute_time()
+    }
+
+    #[cfg(feature = "stats")]
+    pub fn reset_stats(&mut self) {
+        self.counters.reset();
+    }
+
+    /// drop every values but keep their bump memory
+    pub fn clear(&mut self) {
+        #[cfg(feature = "slab")]
+        for val in self
+            .map
+            .drain()
+            .map(|(_, val)| val)
+            .chain(self.retired.drain(..))
+        {
+            self.slab.release(val);
+        }
+
         self.map.clear();
+        self.retired.clear();
+        #[cfg(feature = "deps")]
+        self.deps.clear();
+        self.needs_drop = false;
+        self.generation = next_generation();
+    }
+
+    /// If any stored value needs to be dropped
+    ///
+    /// Stays `true` after such value is removed until the map is cleared or reset.
+    pub const fn needs_drop(&self) -> bool {
+        self.needs_drop
+    }
+
+    /// drop every values and reset bump memory
+    ///
+    /// If no stored value needs to be dropped, destructors are skipped.
+    pub fn reset(&mut self) {
+        if self.needs_drop {
+            self.map.clear();
+            self.retired.clear();
+        } else {
+            self.map.drain().for_each(mem::forget);
+            self.retired.drain(..).for_each(mem::forget);
+        }
+        self.needs_drop = false;
+        #[cfg(feature = "deps")]
+        self.deps.clear();
+        #[cfg(feature = "slab")]
+        self.slab.reset();
+
+        self.peak_bump = self.peak_bump_bytes();
         self.bump.reset();
+        self.allocated = 0;
+        self.generation = next_generation();
     }
 }
 
-impl Default for RawFnMap {
+#[cfg(feature = "deps")]
+impl<S: BuildHasher> RawFnMap<S, TypeKey> {
+    /// remove value stored using key and every values depending on it transitively
+    #[cfg(feature = "deps")]
+    pub fn remove_cascade(&mut self, key: &TypeKey) {
+        let mut pending = alloc::vec![*key];
+
+        while let Some(key) = pending.pop() {
+            self.remove(&key);
+
+            // edges of removed value are recorded again when it is recomputed
+            self.deps.retain(|&(dependent, dependency)| {
+                if dependency == key {
+                    pending.push(dependent);
+                    false
+                } else {
+                    dependent != key
+                }
+            });
+        }
+    }
+}
+
+impl<S: BuildHasher + Default, K: Eq + Hash + Copy> Default for RawFnMap<S, K> {
     fn default() -> Self {
-        Self::new()
+        Self::with_hasher(S::default())
     }
 }
 
-impl Drop for RawFnMap {
+impl<S, K> Drop for RawFnMap<S, K> {
     fn drop(&mut self) {
         self.map.clear();
+        self.retired.clear();
 
         // SAFETY: Manually dropped to ensure allocated objects to drop first
         unsafe { ManuallyDrop::drop(&mut self.bump) }
     }
 }
 
-trait Erased {}
-impl<T: ?Sized> Erased for T {}
+#[cfg(feature = "slab")]
+#[derive(Debug, Default)]
+/// free slots of dropped values grouped by layout
+struct Slab(hashbrown::HashMap<Layout, Vec<NonNull<u8>>>);
+
+#[cfg(feature = "slab")]
+impl Slab {
+    fn take(&mut self, layout: &Layout) -> Option<NonNull<u8>> {
+        self.0.get_mut(layout)?.pop()
+    }
+
+    /// drop value and keep its slot for reuse
+    fn release(&mut self, val: Val) {
+        let layout = val.layout();
+        let slot = val.inner().cast::<u8>();
+        drop(val);
+
+        self.free(layout, slot);
+    }
+
+    fn free(&mut self, layout: Layout, slot: NonNull<u8>) {
+        self.0.entry(layout).or_default().push(slot);
+    }
+
+    fn reset(&mut self) {
+        self.0.clear();
+    }
+}
+
+/// drop value of type `T` pointed by `ptr`
+unsafe fn drop_value<T>(ptr: *mut u8) {
+    // SAFETY: guaranteed by caller
+    unsafe { ptr::drop_in_place(ptr.cast::<T>()) }
+}
 
 #[derive(Debug)]
-#[repr(transparent)]
-struct Val(NonNull<dyn Erased>);
+struct Val {
+    ptr: NonNull<u8>,
+    // type of sized values
+    ty: Option<TypeKey>,
+    type_name: &'static str,
+    drop_fn: unsafe fn(*mut u8),
+    #[cfg(feature = "slab")]
+    layout: Layout,
+
+    version: u64,
+    epoch: u64,
+    accesses: u64,
+    last_access: u64,
+    namespace: Option<u64>,
+
+    #[cfg(feature = "stats")]
+    reads: AtomicU64,
+    // generation of the map on last read
+    #[cfg(feature = "stats")]
+    last_read: AtomicU64,
+
+    #[cfg(feature = "debug-keys")]
+    label: &'static str,
+}
 
 impl Val {
+    pub const fn new(
+        ptr: NonNull<u8>,
+        ty: Option<TypeKey>,
+        layout: Layout,
+        drop_fn: unsafe fn(*mut u8),
+    ) -> Self {
+        #[cfg(not(feature = "slab"))]
+        let _ = layout;
+
+        Self {
+            ptr,
+            ty,
+            type_name: "dynamically sized value",
+            drop_fn,
+            #[cfg(feature = "slab")]
+            layout,
+
+            version: 0,
+            epoch: 0,
+            accesses: 0,
+            last_access: 0,
+            namespace: None,
+
+            #[cfg(feature = "stats")]
+            reads: AtomicU64::new(0),
+            #[cfg(feature = "stats")]
+            last_read: AtomicU64::new(0),
+
+            #[cfg(feature = "debug-keys")]
+            label: "",
+        }
+    }
+
+    /// value of type `T` stored in `layout`
+    pub fn sized<T>(ptr: NonNull<T>, layout: Layout) -> Self {
+        let mut val = Self::new(
+            ptr.cast(),
+            Some(TypeKey::of::<T>()),
+            layout,
+            drop_value::<T>,
+        );
+        val.type_name = any::type_name::<T>();
+
+        val
+    }
+
     pub const fn inner(&self) -> NonNull<()> {
-        self.0.cast()
+        self.ptr.cast()
+    }
+
+    #[cfg(feature = "stats")]
+    fn read(&self, reads: u64, generation: u64) {
+        self.reads.fetch_add(reads, Ordering::Relaxed);
+        self.last_read.store(generation, Ordering::Relaxed);
+    }
+
+    #[cfg(feature = "stats")]
+    fn entry_stats(&self) -> EntryStats {
+        EntryStats {
+            reads: self.reads.load(Ordering::Relaxed),
+            last_access_generation: self.last_read.load(Ordering::Relaxed),
+        }
+    }
+
+    #[cfg(feature = "slab")]
+    pub const fn layout(&self) -> Layout {
+        self.layout
     }
 }
 
 impl Drop for Val {
     fn drop(&mut self) {
         // SAFETY: Safe to drop since it is the only unique pointer
-        unsafe { ptr::drop_in_place(self.0.as_ptr()) }
+        unsafe { (self.drop_fn)(self.ptr.as_ptr()) }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::hash::{BuildHasher, BuildHasherDefault, Hash, Hasher};
+
+    use core::alloc::Layout;
+
+    use type_key::TypeKey;
+
+    use super::{RawFnMap, WideHasher};
+
+    #[derive(Default)]
+    struct CollidingHasher;
+
+    impl Hasher for CollidingHasher {
+        fn finish(&self) -> u64 {
+            0
+        }
+
+        fn write(&mut self, _: &[u8]) {}
+    }
+
+    #[test]
+    fn test_collision() {
+        let mut map = RawFnMap::<BuildHasherDefault<CollidingHasher>>::default();
+
+        let a = TypeKey::of::<u8>();
+        let b = TypeKey::of::<u16>();
+        map.insert(a, 1_i32);
+        map.insert(b, 2_i32);
+
+        #[cfg(debug_assertions)]
+        assert_eq!(map.hash_collisions(), 1);
+
+        // SAFETY: values are inserted with i32
+        unsafe {
+            assert_eq!(*map.get::<i32>(&a).unwrap().as_ref(), 1);
+            assert_eq!(*map.get::<i32>(&b).unwrap().as_ref(), 2);
+        }
+    }
+
+    #[test]
+    fn test_wide_hasher() {
+        // key sharing lower 64 bits
+        struct Wide(u64, u64);
+
+        impl Hash for Wide {
+            fn hash<H: Hasher>(&self, state: &mut H) {
+                state.write_u64(self.0);
+                state.write_u64(self.1);
+            }
+        }
+
+        let hasher = BuildHasherDefault::<WideHasher>::default();
+        assert_ne!(hasher.hash_one(Wide(1, 2)), hasher.hash_one(Wide(1, 3)));
+
+        let mut map = RawFnMap::with_hasher(hasher);
+        let a = TypeKey::of::<u8>();
+        let b = TypeKey::of::<u16>();
+        map.insert(a, 1_i32);
+        map.insert(b, 2_i32);
+
+        // SAFETY: values are inserted with i32
+        unsafe {
+            assert_eq!(*map.get::<i32>(&a).unwrap().as_ref(), 1);
+            assert_eq!(*map.get::<i32>(&b).unwrap().as_ref(), 2);
+        }
+    }
+
+    #[cfg(feature = "wide-key")]
+    #[test]
+    fn test_wide_key() {
+        use core::mem;
+
+        use nohash_hasher::BuildNoHashHasher;
+
+        use super::{wide_bits, KeyHasher, MapKey};
+
+        let a = TypeKey::of::<u8>();
+        let bits = wide_bits(a);
+
+        // flip a bit not hashed by TypeId
+        let narrow = BuildNoHashHasher::<u64>::default();
+        let shift = if bits as u64 == narrow.hash_one(a) {
+            64
+        } else {
+            0
+        };
+        // SAFETY: TypeKey is transparent TypeId of 128 bits, only compared and hashed
+        let b = unsafe { mem::transmute::<u128, TypeKey>(bits ^ (1 << shift)) };
+
+        assert_ne!(a, b);
+        assert_eq!(narrow.hash_one(a), narrow.hash_one(b));
+
+        let hasher = KeyHasher::default();
+        assert_ne!(hasher.hash_one(MapKey(a)), hasher.hash_one(MapKey(b)));
+        assert_ne!(
+            hasher.hash_one(MapKey((a, 0_usize))),
+            hasher.hash_one(MapKey((b, 0_usize)))
+        );
+
+        let mut map = RawFnMap::new();
+        map.insert(a, 1_i32);
+        map.insert(b, 2_i32);
+        #[cfg(debug_assertions)]
+        assert_eq!(map.hash_collisions(), 0);
+
+        // SAFETY: values are inserted with i32
+        unsafe {
+            assert_eq!(*map.get::<i32>(&a).unwrap().as_ref(), 1);
+            assert_eq!(*map.get::<i32>(&b).unwrap().as_ref(), 2);
+        }
+    }
+
+    #[test]
+    fn test_chunk_capacity() {
+        let mut map = RawFnMap::new();
+
+        map.insert(TypeKey::of::<u8>(), 1_u8);
+        let remaining = map.remaining_chunk_capacity();
+        assert!(remaining > 0);
+
+        map.alloc_layout(Layout::from_size_align(remaining - 1, 1).unwrap());
+        assert_eq!(map.remaining_chunk_capacity(), 1);
+
+        map.insert(TypeKey::of::<u64>(), 2_u64);
+        assert!(map.remaining_chunk_capacity() > 1);
+    }
+
+    #[test]
+    fn test_dst() {
+        use core::sync::atomic::{AtomicUsize, Ordering};
+
+        static DROPPED: AtomicUsize = AtomicUsize::new(0);
+
+        // header holding length followed by items
+        let (layout, offset) = Layout::new::<usize>()
+            .extend(Layout::array::<u32>(3).unwrap())
+            .unwrap();
+
+        unsafe fn drop_blob(ptr: *mut u8) {
+            // SAFETY: header is initialized
+            let len = unsafe { ptr.cast::<usize>().read() };
+            DROPPED.fetch_add(len, Ordering::Relaxed);
+        }
+
+        let mut map = RawFnMap::new();
+        let key = TypeKey::of::<u8>();
+
+        // SAFETY: header and every items are initialized
+        let ptr = unsafe {
+            map.insert_dst(
+                key,
+                layout,
+                |ptr| {
+                    ptr.cast::<usize>().write(3);
+                    ptr.add(offset).cast::<[u32; 3]>().write([1, 2, 3]);
+                },
+                drop_blob,
+            )
+        };
+        assert_eq!(map.get::<()>(&key), Some(ptr));
+
+        // SAFETY: blob is initialized with 3 items
+        let items = unsafe {
+            core::slice::from_raw_parts(ptr.as_ptr().cast::<u8>().add(offset).cast::<u32>(), 3)
+        };
+        assert_eq!(items, [1, 2, 3]);
+
+        assert!(map.remove(&key));
+        assert_eq!(DROPPED.load(Ordering::Relaxed), 3);
+    }
+
+    #[test]
+    fn test_needs_drop() {
+        use core::sync::atomic::{AtomicUsize, Ordering};
+
+        static DROPPED: AtomicUsize = AtomicUsize::new(0);
+
+        struct Dropped;
+        impl Drop for Dropped {
+            fn drop(&mut self) {
+                DROPPED.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+
+        let mut map = RawFnMap::new();
+
+        map.insert(TypeKey::of::<u8>(), 1_i32);
+        map.insert(TypeKey::of::<u16>(), [0_u8; 4]);
+        assert!(!map.needs_drop());
+        map.reset();
+
+        map.insert(TypeKey::of::<u8>(), 1_i32);
+        map.insert(TypeKey::of::<u16>(), Dropped);
+        assert!(map.needs_drop());
+
+        map.reset();
+        assert!(!map.needs_drop());
+        assert_eq!(DROPPED.load(Ordering::Relaxed), 1);
+    }
+
+    #[cfg(feature = "slab")]
+    #[test]
+    fn test_slab_replace() {
+        let mut map = RawFnMap::new();
+        let key = TypeKey::of::<u8>();
+
+        // replaced value frees its slot after the new one is allocated
+        map.insert(key, 0_u64);
+        map.insert(key, 1_u64);
+        let allocated = map.allocated_bytes();
+
+        for i in 2..100_u64 {
+            map.insert(key, i);
+            assert_eq!(map.allocated_bytes(), allocated);
+        }
+        // SAFETY: value stored using key is u64
+        assert_eq!(unsafe { *map.get::<u64>(&key).unwrap().as_ref() }, 99);
     }
 }
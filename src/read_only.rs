@@ -0,0 +1,138 @@
+use alloc::vec::Vec;
+
+use crate::{
+    key::{KeyStrategy, TypeIdentity},
+    raw::RawFnMap,
+    FnMap,
+};
+
+#[derive(Debug)]
+/// Read only view of [`FnMap`] created from [`FnMap::read_only`].
+///
+/// It only reads values already stored, so values cannot be computed or dropped through it.
+/// ```
+/// use fn_map::FnMap;
+///
+/// fn one() -> i32 {
+///     1
+/// }
+///
+/// let map = FnMap::new();
+/// map.get(one);
+///
+/// let view = map.read_only();
+/// assert_eq!(view.get_if_present(one), Some(&1));
+/// assert!(!view.contains(|| 2));
+/// assert_eq!(view.len(), 1);
+/// ```
+///
+/// Computing is not possible.
+/// ```compile_fail
+/// use fn_map::FnMap;
+///
+/// let map = FnMap::new();
+/// map.read_only().get(|| 1);
+/// ```
+///
+/// Reset is not possible.
+/// ```compile_fail
+/// use fn_map::FnMap;
+///
+/// let map = FnMap::new();
+/// map.read_only().reset();
+/// ```
+pub struct ReadOnly<'a, S: KeyStrategy = TypeIdentity> {
+    map: &'a FnMap<S>,
+}
+
+impl<'a, S: KeyStrategy> ReadOnly<'a, S> {
+    pub(crate) const fn new(map: &'a FnMap<S>) -> Self {
+        Self { map }
+    }
+
+    /// Get value stored using key
+    ///
+    /// `key_fn` is only used as key and never called.
+    #[inline]
+    pub fn get_if_present<T: 'static + Send>(&self, key_fn: impl FnOnce() -> T) -> Option<&'a T> {
+        let ptr = self.raw().get::<T>(&S::key(&key_fn))?;
+
+        // SAFETY: key strategy guarantees key determines type of the value and reference cannot outlive the map
+        Some(unsafe { ptr.as_ref() })
+    }
+
+    /// Check value is stored using key
+    #[inline]
+    pub fn contains<T: 'static>(&self, key_fn: impl FnOnce() -> T) -> bool {
+        self.raw().entry(&S::key(&key_fn)).is_some()
+    }
+
+    /// Number of stored values
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.raw().len()
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.raw().is_empty()
+    }
+
+    /// Keys of stored values in ascending order
+    #[inline]
+    pub fn dump_sorted(&self) -> Vec<S::Key>
+    where
+        S::Key: Ord,
+    {
+        self.map.dump_sorted()
+    }
+
+    fn raw(&self) -> &'a RawFnMap<S::Hasher, S::Key> {
+        // SAFETY: safe to borrow shared because the map is borrowed shared
+        unsafe { &*self.map.raw.get().cast_const() }
+    }
+}
+
+impl<S: KeyStrategy> Clone for ReadOnly<'_, S> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<S: KeyStrategy> Copy for ReadOnly<'_, S> {}
+
+#[cfg(test)]
+mod tests {
+    use crate::FnMap;
+
+    use super::ReadOnly;
+
+    #[test]
+    fn test_read_only() {
+        fn one() -> i32 {
+            1
+        }
+        fn two() -> i32 {
+            2
+        }
+
+        fn read(view: ReadOnly<'_>) -> (Option<i32>, Option<i32>) {
+            (
+                view.get_if_present(one).copied(),
+                view.get_if_present(two).copied(),
+            )
+        }
+
+        let map = FnMap::new();
+        map.get(one);
+
+        let view = map.read_only();
+        assert_eq!(read(view), (Some(1), None));
+        assert!(view.contains(one));
+        assert!(!view.contains(two));
+        assert_eq!(view.dump_sorted(), map.dump_sorted());
+
+        // view never computes
+        assert_eq!(view.len(), 1);
+    }
+}
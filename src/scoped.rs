@@ -0,0 +1,136 @@
+use core::{
+    cell::{Cell, UnsafeCell},
+    marker::PhantomData,
+};
+
+use alloc::vec::Vec;
+use type_key::TypeKey;
+
+use crate::raw::RawFnMap;
+
+/// Key of value stored in [`ScopedFnMap`]
+///
+/// Key is a `'static` type while its value can borrow data living for `'scope`.
+/// ```
+/// use fn_map::scoped::ScopedKey;
+///
+/// struct Name;
+///
+/// impl<'scope> ScopedKey<'scope> for Name {
+///     type Value = &'scope str;
+/// }
+/// ```
+pub trait ScopedKey<'scope>: 'static {
+    type Value: 'scope;
+}
+
+#[derive(Debug, Default)]
+/// Single thread only FnMap implementation storing values borrowing from `'scope`.
+///
+/// Every values are dropped with the map, so stored references cannot escape the scope.
+/// ```compile_fail
+/// use fn_map::scoped::{ScopedFnMap, ScopedKey};
+///
+/// struct Name;
+///
+/// impl<'scope> ScopedKey<'scope> for Name {
+///     type Value = &'scope str;
+/// }
+///
+/// let escaped: &str = {
+///     let name = String::from("name");
+///     let map = ScopedFnMap::new();
+///
+///     *map.get::<Name>(|| &name)
+/// };
+/// ```
+pub struct ScopedFnMap<'scope> {
+    raw: UnsafeCell<RawFnMap>,
+
+    // invariant over 'scope
+    _scope: PhantomData<Cell<&'scope ()>>,
+}
+
+impl<'scope> ScopedFnMap<'scope> {
+    #[inline]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Get or compute value of key `K`
+    pub fn get<K: ScopedKey<'scope>>(&self, init: impl FnOnce() -> K::Value) -> &K::Value {
+        let key = TypeKey::of::<K>();
+
+        // SAFETY: safe to borrow shared because self is borrowed shared
+        if let Some(ptr) = unsafe { &*self.raw.get().cast_const() }.get(&key) {
+            // SAFETY: pointer is valid and reference cannot outlive more than Self
+            return unsafe { ptr.as_ref() };
+        }
+
+        // accuire value first before borrowing exclusively
+        let value = init();
+
+        // SAFETY: safe to borrow exclusively since no one can borrow more
+        let raw = unsafe { &mut *self.raw.get() };
+        let ptr = raw.alloc_uninit::<K::Value>().cast::<K::Value>();
+
+        // SAFETY: pointer is allocated for the value and values are dropped with Self
+        unsafe {
+            ptr.as_ptr().write(value);
+            raw.insert_ptr(key, ptr).as_ref()
+        }
+    }
+
+    /// Keys of stored values in ascending order
+    ///
+    /// Unlike iteration order of the map, the order is reproducible for same set of keys.
+    pub fn dump_sorted(&self) -> Vec<TypeKey> {
+        // SAFETY: safe to borrow shared because self is borrowed shared
+        unsafe { &*self.raw.get().cast_const() }.dump_sorted()
+    }
+
+    /// Reset stored values
+    #[inline]
+    pub fn reset(&mut self) {
+        self.raw.get_mut().reset();
+    }
+}
+
+impl Drop for ScopedFnMap<'_> {
+    fn drop(&mut self) {
+        // drop values while 'scope is still alive
+        self.raw.get_mut().reset();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::string::String;
+
+    use super::{ScopedFnMap, ScopedKey};
+
+    struct Name;
+
+    impl<'scope> ScopedKey<'scope> for Name {
+        type Value = &'scope str;
+    }
+
+    struct Len;
+
+    impl<'scope> ScopedKey<'scope> for Len {
+        type Value = usize;
+    }
+
+    #[test]
+    fn test_scoped() {
+        let name = String::from("name");
+        let map = ScopedFnMap::new();
+
+        assert_eq!(*map.get::<Name>(|| &name), "name");
+        assert_eq!(*map.get::<Name>(|| unreachable!()), "name");
+        assert_eq!(
+            *map.get::<Len>(|| map.get::<Name>(|| unreachable!()).len()),
+            4
+        );
+    }
+}
@@ -0,0 +1,212 @@
+use core::{
+    cell::{Cell, UnsafeCell},
+    marker::PhantomData,
+    mem::MaybeUninit,
+    ptr::NonNull,
+    sync::atomic::{AtomicBool, AtomicUsize, Ordering},
+};
+
+use alloc::{boxed::Box, vec::Vec};
+use type_key::TypeKey;
+
+use crate::raw::RawFnMap;
+
+type Entry = (TypeKey, NonNull<()>);
+
+#[derive(Debug)]
+/// Single writer, multiple reader FnMap without locks.
+///
+/// Only one [`SpmcWriter`] can exist at a time and it is the only one computing values.
+/// Readers find values published by the writer without locking.
+///
+/// Values are never removed until the map drops and at most `capacity` values can be stored.
+/// Lookup scans every published values, so it is suited for small maps.
+pub struct SpmcFnMap {
+    // owned by the writer
+    raw: UnsafeCell<RawFnMap>,
+
+    // entries below len are published
+    entries: Box<[UnsafeCell<MaybeUninit<Entry>>]>,
+    len: AtomicUsize,
+
+    writing: AtomicBool,
+}
+
+impl SpmcFnMap {
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self {
+            raw: UnsafeCell::new(RawFnMap::new()),
+            entries: (0..capacity)
+                .map(|_| UnsafeCell::new(MaybeUninit::uninit()))
+                .collect(),
+            len: AtomicUsize::new(0),
+            writing: AtomicBool::new(false),
+        }
+    }
+
+    /// Maximum number of stored values
+    pub fn capacity(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Number of published values
+    pub fn len(&self) -> usize {
+        self.len.load(Ordering::Acquire)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Keys of published values in ascending order
+    ///
+    /// Unlike publishing order, the order is reproducible for same set of keys.
+    pub fn dump_sorted(&self) -> Vec<TypeKey> {
+        let len = self.len.load(Ordering::Acquire);
+
+        let mut keys: Vec<TypeKey> = self.entries[..len]
+            .iter()
+            // SAFETY: entries below len are initialized and never written again
+            .map(|entry| unsafe { (*entry.get()).assume_init() }.0)
+            .collect();
+        keys.sort_unstable();
+        keys
+    }
+
+    /// Take writer of the map
+    ///
+    /// Returns `None` if another writer exists.
+    pub fn writer(&self) -> Option<SpmcWriter<'_>> {
+        if self.writing.swap(true, Ordering::Acquire) {
+            return None;
+        }
+
+        Some(SpmcWriter {
+            map: self,
+            _local: PhantomData,
+        })
+    }
+
+    /// Get value published using key
+    #[inline]
+    pub fn get<T: 'static + Send + Sync>(&self, key_fn: impl FnOnce() -> T) -> Option<&T> {
+        let ptr = self.find(&TypeKey::of_val(&key_fn))?;
+
+        // SAFETY: closure type determines type of the value and published values are never dropped until Self drops
+        Some(unsafe { ptr.cast::<T>().as_ref() })
+    }
+
+    fn find(&self, key: &TypeKey) -> Option<NonNull<()>> {
+        let len = self.len.load(Ordering::Acquire);
+
+        self.entries[..len].iter().find_map(|entry| {
+            // SAFETY: entries below len are initialized and never written again
+            let (stored, ptr) = unsafe { (*entry.get()).assume_init() };
+
+            (stored == *key).then_some(ptr)
+        })
+    }
+}
+
+// SAFETY: every values stored are Send and Sync, and only the writer accesses raw map
+#[cfg(not(feature = "no-unsafe-marker"))]
+unsafe impl Send for SpmcFnMap {}
+#[cfg(not(feature = "no-unsafe-marker"))]
+unsafe impl Sync for SpmcFnMap {}
+
+#[derive(Debug)]
+/// Writer of [`SpmcFnMap`]
+pub struct SpmcWriter<'a> {
+    map: &'a SpmcFnMap,
+    _local: PhantomData<Cell<()>>,
+}
+
+impl<'a> SpmcWriter<'a> {
+    /// Get or compute value using key and publish it to readers
+    ///
+    /// Returns `None` if the value is missing and the map is full.
+    pub fn get<T: 'static + Send + Sync>(&self, key_fn: impl FnOnce() -> T) -> Option<&'a T> {
+        let key = TypeKey::of_val(&key_fn);
+
+        if let Some(ptr) = self.map.find(&key) {
+            // SAFETY: closure type determines type of the value
+            return Some(unsafe { ptr.cast::<T>().as_ref() });
+        }
+
+        if self.map.len() == self.map.capacity() {
+            return None;
+        }
+
+        let value = key_fn();
+
+        // value may be published while computing
+        if let Some(ptr) = self.map.find(&key) {
+            // SAFETY: closure type determines type of the value
+            return Some(unsafe { ptr.cast::<T>().as_ref() });
+        }
+
+        let len = self.map.len.load(Ordering::Relaxed);
+        if len == self.map.capacity() {
+            return None;
+        }
+
+        // SAFETY: only the writer accesses raw map
+        let ptr = unsafe { &mut *self.map.raw.get() }.insert(key, value);
+
+        // SAFETY: entry at len is not published yet and only the writer writes entries
+        unsafe { (*self.map.entries[len].get()).write((key, ptr.cast())) };
+        self.map.len.store(len + 1, Ordering::Release);
+
+        // SAFETY: published values are never dropped until map drops
+        Some(unsafe { ptr.as_ref() })
+    }
+}
+
+impl Drop for SpmcWriter<'_> {
+    fn drop(&mut self) {
+        self.map.writing.store(false, Ordering::Release);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[cfg(not(feature = "no-unsafe-marker"))]
+    #[test]
+    fn test_spmc() {
+        extern crate std;
+
+        use std::thread;
+
+        use super::SpmcFnMap;
+
+        let map = SpmcFnMap::with_capacity(3);
+
+        let a = || 1;
+        let b = || 2;
+        let c = || 3;
+
+        thread::scope(|scope| {
+            for _ in 0..4 {
+                scope.spawn(|| {
+                    while map.len() < 3 {
+                        assert!(map.get(a).is_none_or(|value| *value == 1));
+                        assert!(map.get(b).is_none_or(|value| *value == 2));
+                        assert!(map.get(c).is_none_or(|value| *value == 3));
+                    }
+
+                    assert_eq!(map.get(c), Some(&3));
+                });
+            }
+
+            let writer = map.writer().unwrap();
+            assert!(map.writer().is_none());
+
+            assert_eq!(writer.get(a), Some(&1));
+            assert_eq!(writer.get(b), Some(&2));
+            assert_eq!(writer.get(c), Some(&3));
+            assert_eq!(writer.get(|| 4), None);
+        });
+
+        assert!(map.writer().is_some());
+    }
+}
@@ -0,0 +1,240 @@
+use core::sync::atomic::{AtomicU64, Ordering};
+#[cfg(feature = "std")]
+use core::{any::TypeId, time::Duration};
+
+#[cfg(any(feature = "std", feature = "otel"))]
+use alloc::vec::Vec;
+
+#[derive(Debug, Default)]
+/// Statistics counters of a map
+pub(crate) struct Counters {
+    hits: AtomicU64,
+    misses: AtomicU64,
+    evictions: AtomicU64,
+
+    // cumulative time spent computing values of each type
+    #[cfg(feature = "std")]
+    compute_time: Vec<(TypeId, Duration)>,
+}
+
+impl Counters {
+    #[inline]
+    pub fn add_hits(&self, hits: u64) {
+        self.hits.fetch_add(hits, Ordering::Relaxed);
+    }
+
+    #[inline]
+    pub fn miss(&self) {
+        self.misses.fetch_add(1, Ordering::Relaxed);
+    }
+
+    #[inline]
+    pub fn evict(&self) {
+        self.evictions.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn get(&self) -> Stats {
+        Stats {
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+            evictions: self.evictions.load(Ordering::Relaxed),
+        }
+    }
+
+    #[cfg(feature = "std")]
+    pub fn add_compute_time(&mut self, ty: TypeId, elapsed: Duration) {
+        match self
+            .compute_time
+            .iter_mut()
+            .find(|(stored, _)| *stored == ty)
+        {
+            Some((_, time)) => *time += elapsed,
+            None => self.compute_time.push((ty, elapsed)),
+        }
+    }
+
+    #[cfg(feature = "std")]
+    pub fn compute_time(&self) -> &[(TypeId, Duration)] {
+        &self.compute_time
+    }
+
+    pub fn reset(&mut self) {
+        *self = Self::default();
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+/// Snapshot of map statistics
+pub struct Stats {
+    /// Number of lookups found stored value
+    pub hits: u64,
+
+    /// Number of values computed and stored
+    pub misses: u64,
+
+    /// Number of values dropped to make room for new values
+    pub evictions: u64,
+}
+
+#[cfg(feature = "std")]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+/// Snapshot of time spent waiting for lock of a concurrent map
+pub struct LockStats {
+    /// Time waited to read the map
+    pub total_read_wait: Duration,
+
+    /// Time waited to write the map
+    pub total_write_wait: Duration,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+/// Snapshot of statistics of a stored value
+pub struct EntryStats {
+    /// Number of lookups found the value
+    pub reads: u64,
+
+    /// Generation of the map when the value was read last time
+    pub last_access_generation: u64,
+}
+
+#[cfg(feature = "otel")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// Kind of OpenTelemetry instrument a [`Metric`] maps to
+pub enum MetricKind {
+    /// Monotonic sum
+    Counter,
+    /// Last observed value
+    Gauge,
+}
+
+#[cfg(feature = "otel")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// Metric of a map shaped after OpenTelemetry data points
+pub struct Metric {
+    pub name: &'static str,
+    pub description: &'static str,
+    /// Unit in UCUM notation
+    pub unit: &'static str,
+    pub kind: MetricKind,
+    pub value: u64,
+    /// Name of the map given by `with_name`, to be attached as attribute of data points
+    pub map_name: Option<&'static str>,
+}
+
+#[cfg(feature = "otel")]
+impl Metric {
+    /// Metrics of a map using its name, statistics, number of values and allocated bytes
+    pub(crate) fn export(
+        map_name: Option<&'static str>,
+        stats: Stats,
+        len: usize,
+        allocated: usize,
+    ) -> Vec<Metric> {
+        alloc::vec![
+            Metric {
+                name: "fn_map.hits",
+                description: "Number of lookups found stored value",
+                unit: "{lookup}",
+                kind: MetricKind::Counter,
+                value: stats.hits,
+                map_name,
+            },
+            Metric {
+                name: "fn_map.misses",
+                description: "Number of values computed and stored",
+                unit: "{lookup}",
+                kind: MetricKind::Counter,
+                value: stats.misses,
+                map_name,
+            },
+            Metric {
+                name: "fn_map.evictions",
+                description: "Number of values dropped to make room for new values",
+                unit: "{entry}",
+                kind: MetricKind::Counter,
+                value: stats.evictions,
+                map_name,
+            },
+            Metric {
+                name: "fn_map.entries",
+                description: "Number of stored values",
+                unit: "{entry}",
+                kind: MetricKind::Gauge,
+                value: len as u64,
+                map_name,
+            },
+            Metric {
+                name: "fn_map.allocated",
+                description: "Bytes of memory used by stored values since last reset",
+                unit: "By",
+                kind: MetricKind::Gauge,
+                value: allocated as u64,
+                map_name,
+            },
+        ]
+    }
+}
+
+#[cfg(all(test, feature = "otel"))]
+mod tests {
+    use alloc::vec::Vec;
+
+    use crate::{
+        bounded::{BoundedFnMap, Eviction},
+        FnMap,
+    };
+
+    #[test]
+    fn test_export_metrics() {
+        let map = FnMap::new();
+        map.get(|| 1_u64);
+        map.get(|| 2_u64);
+
+        let value = |n| move || n;
+        map.get(value(3_u64));
+        map.get(value(4_u64));
+
+        let metrics = map.export_metrics();
+        let metric = |name| {
+            metrics
+                .iter()
+                .find(|metric| metric.name == name)
+                .unwrap()
+                .value
+        };
+
+        assert_eq!(metric("fn_map.hits"), 1);
+        assert_eq!(metric("fn_map.misses"), 3);
+        assert_eq!(metric("fn_map.evictions"), 0);
+        assert_eq!(metric("fn_map.entries"), 3);
+        assert_eq!(metric("fn_map.allocated"), 24);
+
+        assert!(metrics.iter().all(|metric| metric.map_name.is_none()));
+        let named = FnMap::new().with_name("config");
+        assert!(named
+            .export_metrics()
+            .iter()
+            .all(|metric| metric.map_name == Some("config")));
+
+        let mut registered = Vec::new();
+        map.register_metrics(|metric| registered.push(*metric));
+        assert_eq!(registered, metrics);
+    }
+
+    #[test]
+    fn test_export_evictions() {
+        let mut map = BoundedFnMap::new(1, Eviction::Lru);
+        map.get(|| 1);
+        map.get(|| 2);
+        map.get(|| 3);
+        assert_eq!(map.stats().evictions, 2);
+
+        let mut evictions = None;
+        map.register_metrics(|metric| {
+            if metric.name == "fn_map.evictions" {
+                evictions = Some(metric.value);
+            }
+        });
+        assert_eq!(evictions, Some(2));
+    }
+}
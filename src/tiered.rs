@@ -0,0 +1,73 @@
+use alloc::sync::Arc;
+
+use crate::{ConcurrentFnMap, FnMap};
+
+#[derive(Debug, Default)]
+/// Two level FnMap.
+///
+/// `l1` is a local [`FnMap`] and `l2` is a [`ConcurrentFnMap`] shared between tiers.
+///
+/// Values are always written through to `l2` and copied into `l1` on first access,
+/// so later reads are served from `l1` without touching `l2`.
+pub struct Tiered {
+    pub l1: FnMap,
+    pub l2: Arc<ConcurrentFnMap>,
+}
+
+impl Tiered {
+    #[inline]
+    pub fn new(l2: Arc<ConcurrentFnMap>) -> Self {
+        Self {
+            l1: FnMap::new(),
+            l2,
+        }
+    }
+
+    /// Get or compute value using key
+    ///
+    /// Checks `l1`, then `l2`, then computes and stores value into `l2`.
+    /// Value found in (or stored into) `l2` is promoted into `l1` by cloning.
+    #[inline]
+    pub fn get<T: 'static + Send + Sync + Clone>(&self, key_fn: impl FnOnce() -> T) -> &T {
+        let l2 = &self.l2;
+
+        // closure type is unique for each key_fn type, so it can be used as l1 key
+        self.l1.get(move || l2.get(key_fn).clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::sync::Arc;
+    use core::cell::Cell;
+
+    use crate::ConcurrentFnMap;
+
+    use super::Tiered;
+
+    #[cfg_attr(feature = "no-unsafe-marker", allow(clippy::arc_with_non_send_sync))]
+    #[test]
+    fn test_promotion() {
+        let mut tiered = Tiered::new(Arc::new(ConcurrentFnMap::new()));
+        let computed = Cell::new(0);
+
+        let value = || {
+            computed.set(computed.get() + 1);
+            1
+        };
+
+        let other = Tiered::new(tiered.l2.clone());
+        assert_eq!(*other.get(value), 1);
+        assert_eq!(computed.get(), 1);
+
+        assert_eq!(*tiered.get(value), 1);
+        assert_eq!(computed.get(), 1);
+
+        drop(other);
+        Arc::get_mut(&mut tiered.l2).unwrap().reset();
+
+        // served from l1
+        assert_eq!(*tiered.get(value), 1);
+        assert_eq!(computed.get(), 1);
+    }
+}